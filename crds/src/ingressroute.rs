@@ -1,6 +1,7 @@
 use k8s_openapi::serde::{Deserialize, Serialize};
 use kube::CustomResource;
 use schemars::JsonSchema;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, CustomResource, Serialize, Deserialize, JsonSchema)]
 #[kube(
@@ -8,29 +9,303 @@ use schemars::JsonSchema;
     version = "v1",
     kind = "IngressRoute",
     doc = "IngressRoute is the CRD implementation of a Ferrix HTTP Router",
+    status = "IngressRouteStatus",
     namespaced
 )]
 pub struct IngressRouteSpec {
     pub entrypoint: String,
     pub route: IngressRouteRoute,
     pub tls: Option<String>,
+    pub priority: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteStatus {
+    #[serde(default)]
+    pub conditions: Vec<IngressRouteCondition>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteCondition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub status: String,
+    pub reason: Option<String>,
+    pub message: Option<String>,
+    pub last_transition_time: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct IngressRouteRoute {
-    pub host: String,
+    pub host: Option<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
     pub rules: Vec<IngressRouteRule>,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct IngressRouteRule {
     pub matches: String,
-    pub service: IngressRouteService,
+    /// Rules are evaluated highest priority first. Defaults to the specificity of `matches`:
+    /// twice the length of each Path (plus one) or PathPrefix, the length of each PathRegexp
+    /// and one for any other predicate, summed over `&&` and the minimum over `||`.
+    pub priority: Option<i64>,
+    pub service: Option<IngressRouteService>,
+    #[serde(default)]
+    pub services: Vec<IngressRouteService>,
+    pub redirect: Option<IngressRouteRedirect>,
+    pub canary: Option<IngressRouteCanary>,
+    pub retry: Option<IngressRouteRetry>,
+    pub upgrade: Option<IngressRouteUpgrade>,
+    #[serde(default)]
+    pub middlewares: Vec<IngressRouteMiddleware>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteMiddleware {
+    pub headers: Option<IngressRouteHeaders>,
+    pub strip_prefix: Option<Vec<String>>,
+    pub add_prefix: Option<String>,
+    pub replace_path_regex: Option<IngressRouteReplacePathRegex>,
+    pub rate_limit: Option<IngressRouteRateLimit>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteRateLimit {
+    pub average: u32,
+    pub burst: Option<u32>,
+    #[serde(default)]
+    pub source: RateLimitSource,
+    pub header_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitSource {
+    #[default]
+    #[serde(rename = "clientIP")]
+    ClientIp,
+    Header,
+    User,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteReplacePathRegex {
+    pub regex: String,
+    pub replacement: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteHeaders {
+    pub request: Option<IngressRouteHeaderRules>,
+    pub response: Option<IngressRouteHeaderRules>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteHeaderRules {
+    #[serde(default)]
+    pub add: BTreeMap<String, String>,
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteRedirect {
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub path: Option<String>,
+    pub status_code: Option<u16>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteUpgrade {
+    pub allowed: Option<bool>,
+    pub idle_timeout_seconds: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteRetry {
+    pub attempts: u32,
+    pub on_connect_failure: Option<bool>,
+    /// Retry idempotent requests whose upstream failed or timed out after they were sent.
+    pub on_read_write_failure: Option<bool>,
+    #[serde(default)]
+    pub status_codes: Vec<u16>,
+    pub idempotent_only: Option<bool>,
+    pub per_try_timeout_millis: Option<u64>,
+    pub backoff_millis: Option<u64>,
+    pub max_backoff_millis: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteCanary {
+    pub service: String,
+    #[serde(default)]
+    pub headers: Vec<IngressRouteCanarySelector>,
+    #[serde(default)]
+    pub cookies: Vec<IngressRouteCanarySelector>,
+    pub response_header: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteCanarySelector {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct IngressRouteService {
     pub name: String,
     pub namespace: Option<String>,
     pub port: u16,
+    #[serde(default)]
+    pub scheme: ServiceScheme,
+    #[serde(default)]
+    pub protocol: UpstreamProtocol,
+    pub tls: Option<IngressRouteUpstreamTls>,
+    pub weight: Option<u32>,
+    pub health_check: Option<IngressRouteHealthCheck>,
+    #[serde(default)]
+    pub strategy: IngressRouteStrategy,
+    pub sticky: Option<IngressRouteStickySession>,
+    pub concurrency: Option<IngressRouteConcurrency>,
+    pub outlier_detection: Option<IngressRouteOutlierDetection>,
+    pub timeouts: Option<IngressRouteTimeouts>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteTimeouts {
+    pub connect_millis: Option<u64>,
+    pub read_millis: Option<u64>,
+    pub write_millis: Option<u64>,
+    pub idle_millis: Option<u64>,
+    pub total_millis: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteOutlierDetection {
+    pub consecutive_failures: Option<u32>,
+    pub failure_percentage: Option<u32>,
+    pub minimum_requests: Option<u32>,
+    pub interval_seconds: Option<u64>,
+    pub base_ejection_seconds: Option<u64>,
+    pub max_ejection_seconds: Option<u64>,
+    pub max_ejected_percent: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceScheme {
+    #[default]
+    Http,
+    Https,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamProtocol {
+    #[default]
+    Http1,
+    H2,
+    H2c,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteUpstreamTls {
+    pub ca_config_map: Option<String>,
+    pub ca_secret: Option<String>,
+    pub ca_key: Option<String>,
+    pub insecure_skip_verify: Option<bool>,
+    pub server_name: Option<String>,
+    pub client_certificate_secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteConcurrency {
+    pub max_in_flight: usize,
+    pub max_queued: Option<usize>,
+    pub queue_timeout_millis: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteStickySession {
+    pub cookie_name: Option<String>,
+    pub path: Option<String>,
+    pub secure: Option<bool>,
+    pub http_only: Option<bool>,
+    pub same_site: Option<String>,
+    pub max_age_seconds: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteStrategy {
+    #[serde(rename = "type", default)]
+    pub algorithm: LoadBalancingAlgorithm,
+    pub hash_on: Option<HashSource>,
+    pub hash_key: Option<String>,
+    pub weights: Option<BTreeMap<String, usize>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum LoadBalancingAlgorithm {
+    #[default]
+    RoundRobin,
+    WeightedRoundRobin,
+    LeastRequests,
+    PowerOfTwoChoices,
+    Random,
+    ConsistentHash,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum HashSource {
+    #[default]
+    #[serde(rename = "clientIP")]
+    ClientIp,
+    Header,
+    Cookie,
+    Path,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteHealthCheck {
+    #[serde(default)]
+    pub protocol: HealthCheckProtocol,
+    pub path: Option<String>,
+    pub host: Option<String>,
+    pub expected_status: Option<u16>,
+    pub interval_seconds: Option<u64>,
+    pub timeout_seconds: Option<u64>,
+    pub healthy_threshold: Option<usize>,
+    pub unhealthy_threshold: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckProtocol {
+    #[default]
+    Tcp,
+    Http,
 }
@@ -0,0 +1,29 @@
+use k8s_openapi::serde::{Deserialize, Serialize};
+use kube::CustomResource;
+use schemars::JsonSchema;
+
+#[derive(Debug, Clone, CustomResource, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "ferrix.com",
+    version = "v1",
+    kind = "IngressRouteTCP",
+    doc = "IngressRouteTCP is the CRD implementation of a Ferrix TCP Router",
+    namespaced
+)]
+pub struct IngressRouteTCPSpec {
+    pub entrypoint: String,
+    pub route: IngressRouteTCPRoute,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteTCPRoute {
+    pub sni: Option<String>,
+    pub service: IngressRouteTCPService,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteTCPService {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub port: u16,
+}
@@ -0,0 +1,31 @@
+use k8s_openapi::serde::{Deserialize, Serialize};
+use kube::CustomResource;
+use schemars::JsonSchema;
+
+#[derive(Debug, Clone, CustomResource, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "ferrix.com",
+    version = "v1",
+    kind = "IngressRouteUDP",
+    doc = "IngressRouteUDP is the CRD implementation of a Ferrix UDP Router",
+    namespaced
+)]
+pub struct IngressRouteUDPSpec {
+    pub entrypoint: String,
+    pub route: IngressRouteUDPRoute,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressRouteUDPRoute {
+    pub service: IngressRouteUDPService,
+    pub idle_timeout_seconds: Option<u64>,
+    pub max_sessions: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IngressRouteUDPService {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub port: u16,
+}
@@ -1,3 +1,16 @@
 mod ingressroute;
+mod ingressroutetcp;
+mod ingressrouteudp;
 
-pub use ingressroute::IngressRoute;
+pub use ingressroute::{
+    HashSource, HealthCheckProtocol, IngressRoute, IngressRouteCanary, IngressRouteCanarySelector,
+    IngressRouteConcurrency, IngressRouteCondition, IngressRouteHeaderRules, IngressRouteHeaders,
+    IngressRouteHealthCheck, IngressRouteMiddleware, IngressRouteOutlierDetection,
+    IngressRouteRateLimit, IngressRouteRedirect, IngressRouteReplacePathRegex, IngressRouteRetry,
+    IngressRouteRoute, IngressRouteRule, IngressRouteService, IngressRouteStatus,
+    IngressRouteStickySession, IngressRouteStrategy, IngressRouteTimeouts, IngressRouteUpgrade,
+    IngressRouteUpstreamTls, LoadBalancingAlgorithm, RateLimitSource, ServiceScheme,
+    UpstreamProtocol,
+};
+pub use ingressroutetcp::{IngressRouteTCP, IngressRouteTCPRoute, IngressRouteTCPService};
+pub use ingressrouteudp::{IngressRouteUDP, IngressRouteUDPRoute, IngressRouteUDPService};
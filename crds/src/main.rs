@@ -1,11 +1,16 @@
 use clap::{arg, Command, Parser, ValueEnum};
-use crds::IngressRoute;
+use crds::{IngressRoute, IngressRouteTCP, IngressRouteUDP};
 use kube::CustomResourceExt;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
 enum Crd {
     #[clap(alias = "ingressroute", alias = "ingressRoute")]
     IngressRoute,
+    #[clap(alias = "ingressroutetcp", alias = "ingressRouteTCP")]
+    IngressRouteTcp,
+    #[clap(alias = "ingressrouteudp", alias = "ingressRouteUDP")]
+    IngressRouteUdp,
 }
 
 #[derive(Parser)]
@@ -45,6 +50,8 @@ fn main() {
             let crd = sub_matches.get_one::<Crd>("CRD").unwrap();
             let s = match crd {
                 Crd::IngressRoute => IngressRoute::crd(),
+                Crd::IngressRouteTcp => IngressRouteTCP::crd(),
+                Crd::IngressRouteUdp => IngressRouteUDP::crd(),
             };
             println!("{}", serde_yml::to_string(&s).unwrap());
             if sub_matches.get_flag("copy") {
@@ -1,5 +1,5 @@
 use crate::api::schemas;
-use crate::gateway::RouteTable;
+use crate::gateway::{Action, ConflictTable, Route, RouteTable, WeightedService};
 use axum::extract::State;
 use axum::Json;
 use dashmap::DashMap;
@@ -12,17 +12,81 @@ pub async fn routes(
     let mut routes = HashMap::with_capacity(route_tables.len());
     for table in route_tables.iter() {
         let route_table = table
+            .load()
+            .routes
             .iter()
-            .map(|v| {
-                let lb = v.value();
-                schemas::Route {
-                    host: v.key().clone(),
-                    sni: lb.get_sni(),
-                    backends: lb.clone().get_ip_addresses(),
-                }
+            .map(|(host, rules)| schemas::Route {
+                host: host.clone(),
+                rules: rules.iter().map(rule).collect(),
             })
             .collect();
         routes.insert(table.key().clone(), route_table);
     }
     Json(routes)
 }
+
+pub async fn conflicts(
+    State(conflict_tables): State<Arc<DashMap<String, ConflictTable>>>,
+) -> Json<HashMap<String, Vec<schemas::Conflict>>> {
+    let mut conflicts = HashMap::with_capacity(conflict_tables.len());
+    for table in conflict_tables.iter() {
+        let conflict_table = table
+            .iter()
+            .map(|v| schemas::Conflict {
+                host: v.key().clone(),
+                winner: v.winner.clone(),
+                conflicting: v.losers.clone(),
+            })
+            .collect();
+        conflicts.insert(table.key().clone(), conflict_table);
+    }
+    Json(conflicts)
+}
+
+fn rule(route: &Route) -> schemas::Rule {
+    let mut rule = schemas::Rule {
+        matches: route.matches.clone(),
+        priority: route.priority,
+        canary: None,
+        redirect: None,
+        active_upgrades: route.upgrade.active(),
+        services: Vec::new(),
+    };
+    match &route.action {
+        Action::Forward(split) => {
+            rule.canary = split.canary().map(str::to_string);
+            rule.services = split.services().iter().map(service).collect();
+        }
+        Action::Redirect(redirect) => {
+            rule.redirect = Some(schemas::Redirect {
+                status_code: redirect.status.as_u16(),
+                scheme: redirect.scheme.clone(),
+                host: redirect.host.clone(),
+                port: redirect.port,
+                path: redirect.path.clone(),
+            })
+        }
+    }
+    rule
+}
+
+fn service(service: &WeightedService) -> schemas::Service {
+    let lb = &service.load_balancer;
+    schemas::Service {
+        name: service.name.clone(),
+        weight: service.weight,
+        sni: lb.get_sni(),
+        in_flight: lb.in_flight(),
+        queued: lb.queued(),
+        backends: lb
+            .get_backends()
+            .into_iter()
+            .map(|backend| schemas::Backend {
+                address: backend.address,
+                healthy: backend.healthy,
+                ejected: backend.ejected_for.is_some(),
+                ejected_for_seconds: backend.ejected_for.map(|d| d.as_secs()),
+            })
+            .collect(),
+    }
+}
@@ -2,9 +2,10 @@ mod handlers;
 mod router;
 mod schemas;
 
-use crate::gateway::RouteTable;
+use crate::gateway::{ConflictTable, RouteTable};
 use anyhow::anyhow;
 use async_trait::async_trait;
+use axum::extract::FromRef;
 use dashmap::DashMap;
 use log::{debug, error, info};
 use pingora::server::ShutdownWatch;
@@ -14,12 +15,40 @@ use tokio::net::TcpListener;
 
 pub struct Service {
     port: u16,
+    state: State,
+}
+
+#[derive(Clone)]
+pub struct State {
     route_tables: Arc<DashMap<String, RouteTable>>,
+    conflict_tables: Arc<DashMap<String, ConflictTable>>,
+}
+
+impl FromRef<State> for Arc<DashMap<String, RouteTable>> {
+    fn from_ref(state: &State) -> Self {
+        state.route_tables.clone()
+    }
+}
+
+impl FromRef<State> for Arc<DashMap<String, ConflictTable>> {
+    fn from_ref(state: &State) -> Self {
+        state.conflict_tables.clone()
+    }
 }
 
 impl Service {
-    pub fn new(port: u16, route_tables: Arc<DashMap<String, RouteTable>>) -> Self {
-        Self { port, route_tables }
+    pub fn new(
+        port: u16,
+        route_tables: Arc<DashMap<String, RouteTable>>,
+        conflict_tables: Arc<DashMap<String, ConflictTable>>,
+    ) -> Self {
+        Self {
+            port,
+            state: State {
+                route_tables,
+                conflict_tables,
+            },
+        }
     }
 
     pub async fn run(&self, mut shutdown: ShutdownWatch) -> Result<(), anyhow::Error> {
@@ -28,7 +57,7 @@ impl Service {
             .map_err(|e| anyhow!("error creating listener: {}", e))?;
         info!("API server listening on {}", listener.local_addr().unwrap());
 
-        let app = router::new(self.route_tables.clone());
+        let app = router::new(self.state.clone());
         axum::serve(listener, app)
             .with_graceful_shutdown(async move {
                 if let Err(e) = shutdown.changed().await {
@@ -1,12 +1,10 @@
-use crate::api::handlers;
-use crate::gateway::RouteTable;
+use crate::api::{handlers, State};
 use axum::routing::get;
 use axum::Router;
-use dashmap::DashMap;
-use std::sync::Arc;
 
-pub fn new(route_tables: Arc<DashMap<String, RouteTable>>) -> Router {
+pub fn new(state: State) -> Router {
     Router::new()
         .route("/routes", get(handlers::routes))
-        .with_state(route_tables)
+        .route("/conflicts", get(handlers::conflicts))
+        .with_state(state)
 }
@@ -3,6 +3,49 @@ use serde::Serialize;
 #[derive(Clone, Serialize)]
 pub struct Route {
     pub host: String,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Conflict {
+    pub host: String,
+    pub winner: String,
+    pub conflicting: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Rule {
+    pub matches: String,
+    pub priority: i64,
+    pub canary: Option<String>,
+    pub redirect: Option<Redirect>,
+    pub active_upgrades: usize,
+    pub services: Vec<Service>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Redirect {
+    pub status_code: u16,
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub path: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Service {
+    pub name: String,
+    pub weight: u32,
     pub sni: String,
-    pub backends: Vec<String>,
+    pub in_flight: usize,
+    pub queued: usize,
+    pub backends: Vec<Backend>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Backend {
+    pub address: String,
+    pub healthy: bool,
+    pub ejected: bool,
+    pub ejected_for_seconds: Option<u64>,
 }
@@ -0,0 +1,175 @@
+use crate::k8s::claim;
+use crds::{IngressRoute, IngressRouteCondition, IngressRouteStatus};
+use dashmap::DashMap;
+use k8s_openapi::api::core::v1::ObjectReference;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::{Patch, PatchParams};
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use kube::Api;
+use serde_json::json;
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+const CONDITION_TYPE: &str = "Conflicted";
+const CONFLICT_REASON: &str = "HostConflict";
+const REPORTER: &str = "ferrix";
+
+pub type ConflictTable = Arc<DashMap<String, Conflict>>;
+
+#[derive(Clone)]
+pub struct Conflict {
+    pub winner: String,
+    pub losers: Vec<String>,
+}
+
+// Higher priority wins, then the oldest route, then the route name.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rank(Reverse<i64>, DateTime<Utc>, String);
+
+impl Rank {
+    pub fn new(route: &IngressRoute, name: &str) -> Self {
+        Self(
+            Reverse(route.spec.priority.unwrap_or_default()),
+            claim::created(route),
+            name.to_string(),
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.2
+    }
+}
+
+pub fn stored_condition(route: &IngressRoute) -> Option<IngressRouteCondition> {
+    route
+        .status
+        .as_ref()?
+        .conditions
+        .iter()
+        .find(|condition| condition.type_ == CONDITION_TYPE)
+        .cloned()
+}
+
+// The transition time only moves when the condition status flips.
+pub fn condition(
+    conflicts: &[(String, String)],
+    previous: Option<&IngressRouteCondition>,
+) -> IngressRouteCondition {
+    let (status, reason, message) = match conflicts.is_empty() {
+        true => (
+            "False",
+            "NoConflict",
+            "All hosts are served by this route".to_string(),
+        ),
+        false => (
+            "True",
+            CONFLICT_REASON,
+            conflicts
+                .iter()
+                .map(|(host, winner)| format!("host {} is served by {}", host, winner))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+    };
+    let last_transition_time = previous
+        .filter(|previous| previous.status == status)
+        .and_then(|previous| previous.last_transition_time.clone())
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+    IngressRouteCondition {
+        type_: CONDITION_TYPE.to_string(),
+        status: status.to_string(),
+        reason: Some(reason.to_string()),
+        message: Some(message),
+        last_transition_time: Some(last_transition_time),
+    }
+}
+
+pub fn is_conflicted(condition: &IngressRouteCondition) -> bool {
+    condition.status == "True"
+}
+
+pub async fn report(
+    client: kube::Client,
+    reference: &ObjectReference,
+    condition: &IngressRouteCondition,
+    publish_event: bool,
+) -> Result<(), kube::Error> {
+    let status = IngressRouteStatus {
+        conditions: vec![condition.clone()],
+    };
+
+    let api = Api::<IngressRoute>::namespaced(
+        client.clone(),
+        reference.namespace.as_deref().unwrap_or_default(),
+    );
+    api.patch_status(
+        reference.name.as_deref().unwrap_or_default(),
+        &PatchParams::default(),
+        &Patch::Merge(json!({ "status": status })),
+    )
+    .await?;
+
+    if publish_event {
+        let reporter = Reporter {
+            controller: REPORTER.to_string(),
+            instance: None,
+        };
+        Recorder::new(client, reporter, reference.clone())
+            .publish(Event {
+                type_: EventType::Warning,
+                reason: CONFLICT_REASON.to_string(),
+                note: condition.message.clone(),
+                action: "Route".to_string(),
+                secondary: None,
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{condition, stored_condition};
+    use crate::k8s::testing;
+    use crds::IngressRoute;
+    use serde_json::json;
+
+    const CONFLICT: &[(&str, &str)] = &[("a.example.com", "default/b")];
+
+    fn conflicts(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(host, winner)| (host.to_string(), winner.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn transition_time_is_kept_while_status_is_unchanged() {
+        let mut previous = condition(&conflicts(CONFLICT), None);
+        previous.last_transition_time = Some("2024-01-01T00:00:00+00:00".into());
+
+        let next = condition(&conflicts(CONFLICT), Some(&previous));
+        assert_eq!(next, previous);
+
+        let next = condition(&[], Some(&previous));
+        assert_eq!(next.status, "False");
+        assert_ne!(next.last_transition_time, previous.last_transition_time);
+    }
+
+    #[test]
+    fn reads_the_condition_from_the_stored_status() {
+        let mut route: IngressRoute = testing::resource(
+            "IngressRoute",
+            "a",
+            "2024-01-01T00:00:00Z",
+            json!({"entrypoint": "web", "route": {"host": "a.example.com", "rules": []}}),
+        );
+        assert_eq!(stored_condition(&route), None);
+
+        let stored = condition(&conflicts(CONFLICT), None);
+        route.status = Some(crds::IngressRouteStatus {
+            conditions: vec![stored.clone()],
+        });
+        assert_eq!(stored_condition(&route), Some(stored));
+    }
+}
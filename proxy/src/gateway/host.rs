@@ -0,0 +1,196 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+
+pub const CATCH_ALL: &str = "*";
+
+pub type Captures = BTreeMap<String, String>;
+
+pub enum HostPattern {
+    Exact,
+    Wildcard,
+    Regex(Regex),
+    CatchAll,
+}
+
+impl HostPattern {
+    pub fn parse(host: &str) -> Result<Self, regex::Error> {
+        if host == CATCH_ALL {
+            return Ok(Self::CatchAll);
+        }
+        let (wildcard, name) = match host.strip_prefix("*.") {
+            Some(suffix) => (true, suffix),
+            None => (false, host),
+        };
+        let literal = name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | ':'));
+        match (literal, wildcard) {
+            (true, true) => Ok(Self::Wildcard),
+            (true, false) => Ok(Self::Exact),
+            (false, _) => Regex::new(&format!("(?i)^(?:{})$", host)).map(Self::Regex),
+        }
+    }
+
+    pub fn normalize(&self, host: &str) -> Option<String> {
+        match self {
+            Self::Exact => normalize(host, 0),
+            Self::Wildcard => normalize(&host[2..], 0).map(|suffix| format!("*.{}", suffix)),
+            Self::Regex(_) | Self::CatchAll => Some(host.to_string()),
+        }
+    }
+}
+
+const DEFAULT_PORTS: [u16; 2] = [80, 443];
+
+pub fn normalize(host: &str, entry_point_port: u16) -> Option<String> {
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') || name.ends_with(']') => {
+            (name, Some(port.parse::<u16>().ok()?))
+        }
+        _ => (host, None),
+    };
+    let name = name.trim_end_matches('.');
+    let name = match name.starts_with('[') {
+        true => name.to_ascii_lowercase(),
+        false => idna::domain_to_ascii(name).ok()?,
+    };
+    if name.is_empty() {
+        return None;
+    }
+    match port.filter(|port| !DEFAULT_PORTS.contains(port) && *port != entry_point_port) {
+        Some(port) => Some(format!("{}:{}", name, port)),
+        None => Some(name),
+    }
+}
+
+// An exact host takes precedence over the longest matching wildcard, then regular expressions.
+pub fn find<T>(
+    host: &str,
+    regex_hosts: &[(String, Regex)],
+    get: impl Fn(&str) -> Option<T>,
+) -> Option<(T, Captures)> {
+    if let Some(value) = get(host) {
+        return Some((value, exact(host)));
+    }
+    for (i, _) in host.match_indices('.') {
+        if let Some(value) = get(&format!("*{}", &host[i..])) {
+            return Some((value, wildcard(host, i)));
+        }
+    }
+    regex_hosts.iter().find_map(|(pattern, re)| {
+        let captures = regex(host, re)?;
+        get(pattern).map(|value| (value, captures))
+    })
+}
+
+pub fn exact(host: &str) -> Captures {
+    Captures::from([("0".to_string(), host.to_string())])
+}
+
+pub fn wildcard(host: &str, suffix: usize) -> Captures {
+    let mut captures = exact(host);
+    captures.insert("1".to_string(), host[..suffix].to_string());
+    captures
+}
+
+pub fn regex(host: &str, regex: &Regex) -> Option<Captures> {
+    let groups = regex.captures(host)?;
+    let mut captures = Captures::new();
+    for (i, name) in regex.capture_names().enumerate() {
+        if let Some(value) = groups.get(i) {
+            captures.insert(i.to_string(), value.as_str().to_string());
+            if let Some(name) = name {
+                captures.insert(name.to_string(), value.as_str().to_string());
+            }
+        }
+    }
+    Some(captures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, HostPattern};
+
+    #[test]
+    fn lowercases_and_drops_trailing_dots() {
+        assert_eq!(
+            normalize("Example.COM", 8080).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize("example.com.", 8080).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize("example.com.:81", 8080).as_deref(),
+            Some("example.com:81")
+        );
+    }
+
+    #[test]
+    fn removes_default_and_entry_point_ports() {
+        assert_eq!(
+            normalize("example.com:80", 8080).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize("example.com:443", 8080).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize("example.com:8080", 8080).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize("example.com:8443", 8080).as_deref(),
+            Some("example.com:8443")
+        );
+    }
+
+    #[test]
+    fn converts_internationalized_names_to_punycode() {
+        assert_eq!(
+            normalize("bücher.de", 0).as_deref(),
+            Some("xn--bcher-kva.de")
+        );
+        assert_eq!(
+            normalize("BÜCHER.de:81", 0).as_deref(),
+            Some("xn--bcher-kva.de:81")
+        );
+        assert_eq!(
+            normalize("xn--bcher-kva.de", 0).as_deref(),
+            Some("xn--bcher-kva.de")
+        );
+    }
+
+    #[test]
+    fn handles_ip_literals() {
+        assert_eq!(normalize("10.0.0.1:80", 0).as_deref(), Some("10.0.0.1"));
+        assert_eq!(normalize("[::1]:8443", 0).as_deref(), Some("[::1]:8443"));
+        assert_eq!(normalize("[::1]:443", 0).as_deref(), Some("[::1]"));
+        assert_eq!(normalize("[FE80::1]", 0).as_deref(), Some("[fe80::1]"));
+    }
+
+    #[test]
+    fn rejects_invalid_hosts() {
+        assert_eq!(normalize("", 0), None);
+        assert_eq!(normalize(".", 0), None);
+        assert_eq!(normalize("example.com:http", 0), None);
+        assert_eq!(normalize("example.com:70000", 0), None);
+    }
+
+    #[test]
+    fn normalizes_route_host_patterns() {
+        let normalize = |host: &str| HostPattern::parse(host).unwrap().normalize(host);
+        assert_eq!(normalize("Example.com.").as_deref(), Some("example.com"));
+        assert_eq!(
+            normalize("*.Bücher.de").as_deref(),
+            Some("*.xn--bcher-kva.de")
+        );
+        assert_eq!(
+            normalize("tenant-(.+).example.com").as_deref(),
+            Some("tenant-(.+).example.com")
+        );
+        assert_eq!(normalize("*").as_deref(), Some("*"));
+    }
+}
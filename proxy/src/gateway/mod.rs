@@ -1,26 +1,90 @@
+mod conflict;
+pub mod host;
+mod redirect;
+mod retry;
+mod split;
+mod upgrade;
+
+pub use conflict::{Conflict, ConflictTable};
+pub use redirect::Redirect;
+pub use retry::RetryPolicy;
+pub use split::{TrafficSplit, WeightedService};
+pub use upgrade::Upgrade;
+
+use crate::gateway::conflict::Rank;
+use crate::gateway::host::HostPattern;
+use crate::grpc;
 use crate::k8s;
-use crate::load_balancer::RoundRobinLoadBalancer;
+use crate::load_balancer::{self, ServiceLoadBalancer, Timeouts};
+use crate::matcher::Matcher;
+use crate::middleware::{self, Variables};
+use crate::session;
+use crate::tls::{Certificate, CertificateStore, UpstreamTls};
 use anyhow::anyhow;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use async_trait::async_trait;
+use axum::body::Bytes;
 use axum::http::header::HOST;
-use crds::IngressRoute;
+use crds::{
+    IngressRoute, IngressRouteCondition, IngressRouteRoute, IngressRouteRule, IngressRouteService,
+    ServiceScheme,
+};
 use dashmap::DashMap;
 use futures_util::future::BoxFuture;
-use k8s_openapi::api::core::v1::Endpoints;
+use k8s_openapi::api::core::v1::{ConfigMap, Endpoints, ObjectReference, Secret};
 use kube::runtime::watcher;
 use kube::runtime::watcher::Event;
 use kube::{Api, Resource};
-use log::{debug, error};
-use pingora::http::StatusCode;
+use log::{debug, error, warn};
+use pingora::http::{RequestHeader, ResponseHeader, StatusCode};
 use pingora::prelude::{HttpPeer, Session};
 use pingora::proxy::ProxyHttp;
+use regex::Regex;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Notify;
 
+const DEFAULT_CA_KEY: &str = "ca.crt";
+const GATEWAY_TIMEOUT_BODY: &str = "upstream request timed out\n";
+
 #[derive(Clone)]
 pub struct SharedGateway(Arc<Gateway>);
 
-pub type RouteTable = Arc<DashMap<String, RoundRobinLoadBalancer>>;
+pub type RouteTable = Arc<ArcSwap<HostTable>>;
+
+#[derive(Clone, Default)]
+pub struct HostTable {
+    pub routes: HashMap<String, Vec<Route>>,
+    pub regex_hosts: Vec<(String, Regex)>,
+    owners: HashMap<String, String>,
+}
+
+impl HostTable {
+    fn remove(&mut self, host: &str) {
+        self.routes.remove(host);
+        self.regex_hosts.retain(|(pattern, _)| pattern != host);
+        self.owners.remove(host);
+    }
+}
+
+#[derive(Clone)]
+pub struct Route {
+    pub name: String,
+    pub matches: String,
+    pub priority: i64,
+    pub matcher: Arc<Matcher>,
+    pub action: Action,
+    pub middleware: Arc<middleware::Chain>,
+    pub retry: Option<Arc<RetryPolicy>>,
+    pub upgrade: Upgrade,
+}
+
+#[derive(Clone)]
+pub enum Action {
+    Forward(TrafficSplit),
+    Redirect(Redirect),
+}
 
 impl SharedGateway {
     pub fn new(gateway: Gateway) -> Self {
@@ -30,6 +94,10 @@ impl SharedGateway {
     pub fn get_route_table(&self) -> RouteTable {
         self.0.get_route_table()
     }
+
+    pub fn get_conflicts(&self) -> ConflictTable {
+        self.0.get_conflicts()
+    }
 }
 
 #[async_trait]
@@ -40,6 +108,14 @@ impl ProxyHttp for SharedGateway {
         self.0.as_ref().new_ctx()
     }
 
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<bool> {
+        self.0.as_ref().request_filter(session, ctx).await
+    }
+
     async fn upstream_peer(
         &self,
         session: &mut Session,
@@ -47,18 +123,155 @@ impl ProxyHttp for SharedGateway {
     ) -> pingora::Result<Box<HttpPeer>> {
         self.0.as_ref().upstream_peer(session, ctx).await
     }
+
+    fn fail_to_connect(
+        &self,
+        session: &mut Session,
+        peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        e: Box<pingora::Error>,
+    ) -> Box<pingora::Error> {
+        self.0.as_ref().fail_to_connect(session, peer, ctx, e)
+    }
+
+    fn error_while_proxy(
+        &self,
+        peer: &HttpPeer,
+        session: &mut Session,
+        e: Box<pingora::Error>,
+        ctx: &mut Self::CTX,
+        client_reused: bool,
+    ) -> Box<pingora::Error> {
+        self.0
+            .as_ref()
+            .error_while_proxy(peer, session, e, ctx, client_reused)
+    }
+
+    async fn fail_to_proxy(
+        &self,
+        session: &mut Session,
+        e: &pingora::Error,
+        ctx: &mut Self::CTX,
+    ) -> u16 {
+        self.0.as_ref().fail_to_proxy(session, e, ctx).await
+    }
+
+    async fn request_body_filter(
+        &self,
+        session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        self.0
+            .as_ref()
+            .request_body_filter(session, body, end_of_stream, ctx)
+            .await
+    }
+
+    fn response_body_filter(
+        &self,
+        session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<Option<Duration>> {
+        self.0
+            .as_ref()
+            .response_body_filter(session, body, end_of_stream, ctx)
+    }
+
+    async fn upstream_request_filter(
+        &self,
+        session: &mut Session,
+        upstream_request: &mut RequestHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        self.0
+            .as_ref()
+            .upstream_request_filter(session, upstream_request, ctx)
+            .await
+    }
+
+    async fn response_filter(
+        &self,
+        session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        self.0
+            .as_ref()
+            .response_filter(session, upstream_response, ctx)
+            .await
+    }
+}
+
+#[derive(Default)]
+pub struct Context {
+    route: Option<Route>,
+    attempts: usize,
+    deadline: Option<Instant>,
+    load_balancer: Option<ServiceLoadBalancer>,
+    variant: Option<(String, String)>,
+    middleware: Option<(Arc<middleware::Chain>, Variables)>,
+    upgrade: Option<upgrade::UpgradeGuard>,
+    upstream: load_balancer::Context,
+}
+
+struct ManagedRoute {
+    reference: ObjectReference,
+    rank: Rank,
+    hosts: Vec<String>,
+    routes: Vec<Route>,
+    certificate: Arc<ArcSwapOption<Certificate>>,
+    generation: Option<i64>,
+    condition: Option<IngressRouteCondition>,
+    services: Vec<ManagedService>,
+    watches: Vec<Arc<Notify>>,
+}
+
+#[derive(Clone)]
+struct ManagedService {
+    key: serde_json::Value,
+    load_balancer: ServiceLoadBalancer,
+    watch: Arc<Notify>,
+}
+
+impl ManagedRoute {
+    // Watches in `keep` feed load balancers carried over to a rebuilt route, so they keep running.
+    fn stop_watches(&self, keep: &[Arc<Notify>]) {
+        self.watches
+            .iter()
+            .filter(|n| !keep.iter().any(|k| Arc::ptr_eq(n, k)))
+            .for_each(|n| n.notify_one());
+    }
 }
 
 pub struct Gateway {
     route_table: RouteTable,
-    managed_objects: Arc<DashMap<String, (String, Arc<Notify>)>>,
+    managed_objects: Arc<DashMap<String, ManagedRoute>>,
+    conflicts: ConflictTable,
+    port: u16,
+    certificates: Option<CertificateStore>,
+    redirect: Option<Redirect>,
+    timeouts: Timeouts,
 }
 
 impl Gateway {
-    pub fn new() -> Self {
+    pub fn new(
+        port: u16,
+        certificates: Option<CertificateStore>,
+        redirect: Option<Redirect>,
+        timeouts: Timeouts,
+    ) -> Self {
         Self {
-            route_table: Arc::new(DashMap::new()),
+            route_table: Arc::new(ArcSwap::from_pointee(HostTable::default())),
             managed_objects: Arc::new(DashMap::new()),
+            conflicts: Arc::new(DashMap::new()),
+            port,
+            certificates,
+            redirect,
+            timeouts,
         }
     }
 
@@ -66,6 +279,10 @@ impl Gateway {
         self.route_table.clone()
     }
 
+    pub fn get_conflicts(&self) -> ConflictTable {
+        self.conflicts.clone()
+    }
+
     pub fn update_route_tables(
         route_tables: Arc<DashMap<String, SharedGateway>>,
     ) -> impl Fn(kube::client::Client, IngressRoute) -> BoxFuture<'static, Result<(), anyhow::Error>>
@@ -87,6 +304,30 @@ impl Gateway {
         }
     }
 
+    pub fn delete_routes(
+        route_tables: Arc<DashMap<String, SharedGateway>>,
+    ) -> impl Fn(kube::client::Client, IngressRoute) -> BoxFuture<'static, Result<(), anyhow::Error>>
+           + Send
+           + Sync
+           + 'static {
+        move |k8s_client, route| {
+            let route_tables = route_tables.clone();
+            Box::pin(async move {
+                let gateways = route_tables
+                    .iter()
+                    .map(|v| v.value().clone())
+                    .collect::<Vec<_>>();
+                if let Some(route_id) = route.meta().uid.as_deref() {
+                    for gateway in gateways {
+                        gateway.0.delete_route(k8s_client.clone(), route_id).await;
+                    }
+                }
+
+                Ok(())
+            })
+        }
+    }
+
     async fn update_route_table(
         &self,
         k8s_client: kube::Client,
@@ -94,80 +335,427 @@ impl Gateway {
     ) -> Result<(), anyhow::Error> {
         let route_meta = route.meta().clone();
         let route_id = route_meta.uid.clone().unwrap();
-        let host = route.spec.route.host.clone();
-        let route_table = self.route_table.clone();
-        let managed_objects = self.managed_objects.clone();
-        let route = route.clone();
+        let namespace = route_meta.namespace.clone().unwrap();
+        let route_name = format!(
+            "{}/{}",
+            namespace,
+            route_meta.name.clone().unwrap_or_default()
+        );
 
-        if route_meta.deletion_timestamp.is_some() {
-            Self::delete_route(&host, route_table.clone(), managed_objects.clone());
+        // Status patches do not bump the generation and would otherwise rebuild the route.
+        if route_meta.generation.is_some()
+            && self
+                .managed_objects
+                .get(&route_id)
+                .is_some_and(|previous| previous.generation == route_meta.generation)
+        {
             return Ok(());
         }
 
-        let notify = Arc::new(Notify::new());
-        let (sni, ips) =
-            Self::get_endpoints_from_route(k8s_client, route, notify.clone(), route_table.clone())
-                .await
-                .map_err(|e| anyhow!("unable to get endpoints for new service: {}", e))?;
-        let lb = RoundRobinLoadBalancer::try_from_iter(&sni, ips)?;
+        let hosts = Self::route_hosts(&route.spec.route)?;
+        let rules = route
+            .spec
+            .route
+            .rules
+            .iter()
+            .map(|rule| {
+                let matcher = rule
+                    .matches
+                    .parse::<Matcher>()
+                    .map_err(|e| anyhow!("invalid matches expression '{}': {}", rule.matches, e))?;
+                let chain =
+                    middleware::Chain::try_from(rule.middlewares.as_slice()).map_err(|e| {
+                        anyhow!("invalid middleware for rule '{}': {}", rule.matches, e)
+                    })?;
+                let redirect = rule
+                    .redirect
+                    .as_ref()
+                    .map(Redirect::try_from)
+                    .transpose()
+                    .map_err(|e| anyhow!("invalid redirect for rule '{}': {}", rule.matches, e))?;
+                let retry = rule
+                    .retry
+                    .as_ref()
+                    .map(RetryPolicy::try_from)
+                    .transpose()
+                    .map_err(|e| anyhow!("invalid retry for rule '{}': {}", rule.matches, e))?;
+                Ok((
+                    rule,
+                    matcher,
+                    chain,
+                    redirect,
+                    retry,
+                    Self::rule_services(rule)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
 
-        if let Some((object_host, notify)) = managed_objects.get(&host).map(|v| v.clone()) {
-            if object_host == host {
-                route_table.alter(&object_host, |_, _| lb);
-                return Ok(());
+        let mut routes = Vec::with_capacity(rules.len());
+        let mut object = ManagedRoute {
+            reference: route.object_ref(&()),
+            rank: Rank::new(&route, &route_name),
+            hosts,
+            routes: Vec::new(),
+            certificate: Arc::new(ArcSwapOption::empty()),
+            generation: route_meta.generation,
+            condition: conflict::stored_condition(&route),
+            services: Vec::with_capacity(rules.len()),
+            watches: Vec::with_capacity(rules.len()),
+        };
+        let previous_services = self
+            .managed_objects
+            .get(&route_id)
+            .map(|previous| previous.services.clone())
+            .unwrap_or_default();
+        let previous_watches = previous_services
+            .iter()
+            .map(|service| service.watch.clone())
+            .collect::<Vec<_>>();
+        for (rule, matcher, chain, redirect, retry, services) in rules {
+            let mut weighted_services = Vec::with_capacity(services.len());
+            for service in services {
+                let key = Self::service_key(&namespace, service);
+                let managed = match previous_services.iter().find(|s| s.key == key) {
+                    Some(previous) => previous.clone(),
+                    None => {
+                        let notify = Arc::new(Notify::new());
+                        match Self::watch_service_endpoints(
+                            k8s_client.clone(),
+                            &namespace,
+                            service,
+                            notify.clone(),
+                        )
+                        .await
+                        {
+                            Ok(load_balancer) => ManagedService {
+                                key,
+                                load_balancer,
+                                watch: notify,
+                            },
+                            Err(e) => {
+                                object.stop_watches(&previous_watches);
+                                return Err(anyhow!(
+                                    "unable to get endpoints for new service: {}",
+                                    e
+                                ));
+                            }
+                        }
+                    }
+                };
+
+                object.watches.push(managed.watch.clone());
+                weighted_services.push(WeightedService {
+                    name: service.name.clone(),
+                    weight: service.weight.unwrap_or(1),
+                    load_balancer: managed.load_balancer.clone(),
+                });
+                object.services.push(managed);
             }
 
-            Self::delete_route(&object_host, route_table.clone(), managed_objects.clone());
-            route_table.insert(host.clone(), lb);
-            managed_objects.alter(&route_id, |_, _| (host, notify));
-        } else {
-            route_table.insert(host.clone(), lb);
-            managed_objects.insert(route_id, (host, notify));
+            routes.push(Route {
+                name: route_name.clone(),
+                matches: rule.matches.clone(),
+                priority: rule.priority.unwrap_or_else(|| matcher.specificity()),
+                matcher: Arc::new(matcher),
+                action: match redirect {
+                    Some(redirect) => Action::Redirect(redirect),
+                    None => Action::Forward(
+                        TrafficSplit::new(weighted_services, rule.canary.as_ref())
+                            .inspect_err(|_| object.stop_watches(&previous_watches))?,
+                    ),
+                },
+                middleware: Arc::new(chain),
+                retry: retry.map(Arc::new),
+                upgrade: rule.upgrade.as_ref().map(Upgrade::from).unwrap_or_default(),
+            });
+        }
+        routes.sort_by_key(|route| std::cmp::Reverse(route.priority));
+        if let Some(previous) = self.managed_objects.get(&route_id) {
+            for route in routes.iter_mut() {
+                if let Some(old) = previous
+                    .routes
+                    .iter()
+                    .find(|old| old.matches == route.matches)
+                {
+                    route.upgrade.inherit(&old.upgrade);
+                    if let Some(chain) = Arc::get_mut(&mut route.middleware) {
+                        chain.inherit(&old.middleware);
+                    }
+                }
+            }
+            object.condition = previous.condition.clone();
+        }
+        object.routes = routes;
+
+        if let (Some(certificates), Some(secret)) = (&self.certificates, &route.spec.tls) {
+            let notify = Arc::new(Notify::new());
+            let publish = {
+                let route_id = route_id.clone();
+                let hosts = object.hosts.clone();
+                let certificate = object.certificate.clone();
+                let route_table = self.route_table.clone();
+                let certificates = certificates.clone();
+                move || {
+                    let table = route_table.load();
+                    for host in &hosts {
+                        if table.owners.get(host) != Some(&route_id) {
+                            continue;
+                        }
+                        match certificate.load_full() {
+                            Some(c) => certificates.insert(host.clone(), c),
+                            None => certificates.remove(host).map(|(_, c)| c),
+                        };
+                    }
+                }
+            };
+            if let Err(e) = Self::watch_tls_secret(
+                k8s_client.clone(),
+                &namespace,
+                secret,
+                object.certificate.clone(),
+                publish,
+                notify.clone(),
+            )
+            .await
+            {
+                object.stop_watches(&previous_watches);
+                return Err(anyhow!("unable to load TLS secret {}: {}", secret, e));
+            }
+            object.watches.push(notify);
         }
 
+        let mut hosts = object.hosts.clone();
+        let watches = object.watches.clone();
+        if let Some(previous) = self.managed_objects.insert(route_id, object) {
+            previous.stop_watches(&watches);
+            for host in previous.hosts {
+                if !hosts.contains(&host) {
+                    hosts.push(host);
+                }
+            }
+        }
+        self.resolve_hosts(&hosts);
+        self.report_conflicts(k8s_client, &hosts).await;
+
         Ok(())
     }
 
-    fn delete_route(
-        host: &str,
-        route_table: RouteTable,
-        managed_objects: Arc<DashMap<String, (String, Arc<Notify>)>>,
-    ) {
-        let notify = managed_objects.get(host).map(|v| v.clone().1).unwrap();
-        notify.notify_one();
+    fn route_hosts(route: &IngressRouteRoute) -> Result<Vec<String>, anyhow::Error> {
+        let mut hosts = Vec::new();
+        for host in route.host.iter().chain(&route.hosts) {
+            let pattern = HostPattern::parse(host)
+                .map_err(|e| anyhow!("invalid host pattern '{}': {}", host, e))?;
+            let host = pattern
+                .normalize(host)
+                .ok_or_else(|| anyhow!("invalid host '{}'", host))?;
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        if hosts.is_empty() {
+            return Err(anyhow!("route must set host or hosts"));
+        }
+        Ok(hosts)
+    }
+
+    // Weights only steer the traffic split, so a weight change keeps the load balancer and with it
+    // the concurrency permits, outlier ejections and health of its backends.
+    fn service_key(namespace: &str, service: &IngressRouteService) -> serde_json::Value {
+        let service = IngressRouteService {
+            weight: None,
+            ..service.clone()
+        };
+        serde_json::json!({ "namespace": namespace, "service": service })
+    }
+
+    fn rule_services(rule: &IngressRouteRule) -> Result<Vec<&IngressRouteService>, anyhow::Error> {
+        match (&rule.service, rule.services.is_empty()) {
+            (None, true) if rule.redirect.is_some() => Ok(Vec::new()),
+            _ if rule.redirect.is_some() => Err(anyhow!(
+                "rule '{}' must set only one of redirect or service(s)",
+                rule.matches
+            )),
+            (Some(service), true) => Ok(vec![service]),
+            (None, false) => Ok(rule.services.iter().collect()),
+            (Some(_), false) => Err(anyhow!(
+                "rule '{}' must set only one of service or services",
+                rule.matches
+            )),
+            (None, true) => Err(anyhow!("rule '{}' has no backend service", rule.matches)),
+        }
+    }
+
+    fn find_route(&self, session: &Session, host: &str) -> Option<(Route, host::Captures)> {
+        let table = self.route_table.load();
+        let find = |pattern: &str| {
+            table.routes.get(pattern).and_then(|routes| {
+                routes
+                    .iter()
+                    .find(|route| route.matcher.matches(session))
+                    .cloned()
+            })
+        };
 
-        route_table.remove(host);
-        managed_objects.remove(host);
+        host::find(host, &table.regex_hosts, find)
+            .or_else(|| find(host::CATCH_ALL).map(|route| (route, host::exact(host))))
     }
 
-    async fn get_endpoints_from_route(
+    async fn delete_route(&self, k8s_client: kube::Client, route_id: &str) {
+        if let Some((_, object)) = self.managed_objects.remove(route_id) {
+            object.stop_watches(&[]);
+            self.resolve_hosts(&object.hosts);
+            self.report_conflicts(k8s_client, &object.hosts).await;
+        }
+    }
+
+    // Route table updates are serialized by the watcher, so the new table is built from the
+    // current one and published with a single store.
+    fn resolve_hosts(&self, hosts: &[String]) {
+        let mut table = HostTable::clone(&self.route_table.load());
+        for host in hosts {
+            let mut claims = self
+                .managed_objects
+                .iter()
+                .filter(|object| object.hosts.contains(host))
+                .map(|object| (object.rank.clone(), object.key().clone()))
+                .collect::<Vec<_>>();
+            claims.sort();
+            let Some(winner) = claims
+                .first()
+                .and_then(|(_, id)| self.managed_objects.get(id))
+            else {
+                table.remove(host);
+                self.conflicts.remove(host);
+                if let Some(certificates) = &self.certificates {
+                    certificates.remove(host);
+                }
+                continue;
+            };
+
+            if let Ok(HostPattern::Regex(regex)) = HostPattern::parse(host) {
+                table.regex_hosts.retain(|(pattern, _)| pattern != host);
+                table.regex_hosts.push((host.clone(), regex));
+            }
+            table.owners.insert(host.clone(), winner.key().clone());
+            table.routes.insert(host.clone(), winner.routes.clone());
+            if let Some(certificates) = &self.certificates {
+                match winner.certificate.load_full() {
+                    Some(c) => certificates.insert(host.clone(), c),
+                    None => certificates.remove(host).map(|(_, c)| c),
+                };
+            }
+
+            if claims.len() == 1 {
+                self.conflicts.remove(host);
+                continue;
+            }
+            let losers = claims[1..]
+                .iter()
+                .map(|(rank, _)| rank.name().to_string())
+                .collect::<Vec<_>>();
+            warn!(
+                "Host {} is claimed by {} and {}, serving {}",
+                host,
+                winner.rank.name(),
+                losers.join(", "),
+                winner.rank.name()
+            );
+            self.conflicts.insert(
+                host.clone(),
+                Conflict {
+                    winner: winner.rank.name().to_string(),
+                    losers,
+                },
+            );
+        }
+        table
+            .regex_hosts
+            .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        self.route_table.store(Arc::new(table));
+    }
+
+    async fn report_conflicts(&self, k8s_client: kube::Client, hosts: &[String]) {
+        let mut reports = Vec::new();
+        for object in self.managed_objects.iter() {
+            if !object.hosts.iter().any(|host| hosts.contains(host)) {
+                continue;
+            }
+            let conflicts = object
+                .hosts
+                .iter()
+                .filter_map(|host| {
+                    let conflict = self.conflicts.get(host)?;
+                    conflict
+                        .losers
+                        .iter()
+                        .any(|loser| loser == object.rank.name())
+                        .then(|| (host.clone(), conflict.winner.clone()))
+                })
+                .collect::<Vec<_>>();
+            let condition = conflict::condition(&conflicts, object.condition.as_ref());
+            if object.condition.as_ref() == Some(&condition) {
+                continue;
+            }
+            let publish_event = conflict::is_conflicted(&condition)
+                && !object
+                    .condition
+                    .as_ref()
+                    .is_some_and(conflict::is_conflicted);
+            reports.push((
+                object.key().clone(),
+                object.reference.clone(),
+                object.rank.name().to_string(),
+                condition,
+                publish_event,
+            ));
+        }
+
+        // The condition is only stored once reported, so a failed report is retried next time.
+        for (route_id, reference, name, condition, publish_event) in reports {
+            match conflict::report(k8s_client.clone(), &reference, &condition, publish_event).await
+            {
+                Ok(_) => {
+                    if let Some(mut object) = self.managed_objects.get_mut(&route_id) {
+                        object.condition = Some(condition);
+                    }
+                }
+                Err(e) => error!("Unable to report host conflicts for route {}: {}", name, e),
+            }
+        }
+    }
+
+    async fn watch_tls_secret<F>(
         client: kube::Client,
-        route: IngressRoute,
+        namespace: &str,
+        secret_name: &str,
+        certificate: Arc<ArcSwapOption<Certificate>>,
+        publish: F,
         notify: Arc<Notify>,
-        route_table: RouteTable,
-    ) -> Result<(String, Vec<String>), kube::Error> {
-        let backup_namespace = route.meta().namespace.clone().unwrap();
-        let service = route.spec.route.rules[0].service.clone();
-        let namespace = service.namespace.clone().unwrap_or(backup_namespace);
-        let api = Api::<Endpoints>::namespaced(client.clone(), &namespace);
-        let ep = api.get(&service.name).await?;
+    ) -> Result<(), anyhow::Error>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let api = Api::<Secret>::namespaced(client.clone(), namespace);
+        let secret = api.get(secret_name).await?;
+        let (cert, key) = k8s::secrets::get_tls_pair(&secret)?;
+        certificate.store(Some(Arc::new(Certificate::from_pem(&cert, &key)?)));
+        let secret_name = secret_name.to_string();
 
-        let sni = format!("{}.{}.svc.cluster.local", service.name, namespace.clone());
-        let sni_clone = sni.clone();
-        let host = route.spec.route.host.clone();
+        let watch_opts = watcher::Config {
+            field_selector: Some(format!(
+                "metadata.name={},metadata.namespace={}",
+                secret_name, namespace
+            )),
+            ..Default::default()
+        };
         tokio::spawn(async move {
-            let watch_opts = watcher::Config {
-                field_selector: Some(format!("metadata.name={}", service.name)),
-                ..Default::default()
+            let mut watch = match k8s::watcher::create::<Secret>(client.clone(), watch_opts).await {
+                Ok(w) => w,
+                Err(e) => {
+                    error!("Unable to create TLS secret watcher: {}", e);
+                    return;
+                }
             };
-            let mut watch =
-                match k8s::watcher::create::<Endpoints>(client.clone(), watch_opts).await {
-                    Ok(w) => w,
-                    Err(e) => {
-                        error!("Unable to create endpoints watcher: {}", e);
-                        return;
-                    }
-                };
 
             loop {
                 tokio::select! {
@@ -176,69 +764,787 @@ impl Gateway {
                     }
                     event = watch.recv() => match event {
                         Some(event) => {
-                            let endpoints = match event {
-                                Event::Applied(e) => vec![e],
-                                Event::Deleted(_) => continue,
-                                Event::Restarted(e) => e
+                            let secrets = match event {
+                                Event::Applied(s) => vec![s],
+                                Event::Deleted(_) => {
+                                    warn!("TLS secret {} was deleted", secret_name);
+                                    certificate.store(None);
+                                    publish();
+                                    continue;
+                                }
+                                Event::Restarted(s) => s
                             };
 
-                            let ep = endpoints.last().unwrap();
-                            let ips = k8s::endpoints::get_ip_addresses(ep.clone(), service.port);
-                            match RoundRobinLoadBalancer::try_from_iter(&sni_clone, ips) {
-                                Ok(lb) => {
-                                    debug!("Load balancer updated with new endpoint addresses");
-                                    route_table.alter(&host, |_, _| lb);
+                            let Some(secret) = secrets.last() else {
+                                continue;
+                            };
+                            let updated = k8s::secrets::get_tls_pair(secret)
+                                .and_then(|(cert, key)| Ok(Certificate::from_pem(&cert, &key)?));
+                            match updated {
+                                Ok(c) => {
+                                    debug!("TLS certificate updated from secret {}", secret_name);
+                                    certificate.store(Some(Arc::new(c)));
+                                    publish();
                                 }
-                                Err(e) => error!("Unable to update load balancer with new endpoints: {}", e),
+                                Err(e) => error!("Unable to update TLS certificate from secret {}: {}", secret_name, e),
                             }
                         }
-                        None => continue
+                        None => break
                     }
                 }
             }
         });
 
-        let ips = k8s::endpoints::get_ip_addresses(ep, service.port);
-        Ok((sni, ips))
+        Ok(())
+    }
+
+    async fn load_upstream_tls(
+        client: kube::Client,
+        namespace: &str,
+        service: &IngressRouteService,
+    ) -> Result<UpstreamTls, anyhow::Error> {
+        let Some(spec) = &service.tls else {
+            return Ok(UpstreamTls::new(true, None));
+        };
+
+        let verify = !spec.insecure_skip_verify.unwrap_or(false);
+        let mut tls = UpstreamTls::new(verify, spec.server_name.clone());
+        let ca_key = spec.ca_key.as_deref().unwrap_or(DEFAULT_CA_KEY);
+        let ca = match (&spec.ca_config_map, &spec.ca_secret) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "service {} must set only one of caConfigMap or caSecret",
+                    service.name
+                ))
+            }
+            (Some(name), None) => {
+                let config_map = Api::<ConfigMap>::namespaced(client.clone(), namespace)
+                    .get(name)
+                    .await?;
+                Some(k8s::config_maps::get_key(&config_map, ca_key)?)
+            }
+            (None, Some(name)) => {
+                let secret = Api::<Secret>::namespaced(client.clone(), namespace)
+                    .get(name)
+                    .await?;
+                Some(k8s::secrets::get_key(&secret, ca_key)?)
+            }
+            (None, None) => None,
+        };
+        if let Some(ca) = ca {
+            tls = tls.with_ca(&ca)?;
+        }
+        if let Some(name) = &spec.client_certificate_secret {
+            let secret = Api::<Secret>::namespaced(client, namespace)
+                .get(name)
+                .await?;
+            let (cert, key) = k8s::secrets::get_tls_pair(&secret)?;
+            tls = tls.with_client_certificate(&cert, &key)?;
+        }
+        Ok(tls)
+    }
+
+    async fn watch_service_endpoints(
+        client: kube::Client,
+        route_namespace: &str,
+        service: &IngressRouteService,
+        notify: Arc<Notify>,
+    ) -> Result<ServiceLoadBalancer, anyhow::Error> {
+        let namespace = service
+            .namespace
+            .clone()
+            .unwrap_or(route_namespace.to_string());
+        let api = Api::<Endpoints>::namespaced(client.clone(), &namespace);
+        let ep = api.get(&service.name).await?;
+
+        let sni = format!("{}.{}.svc.cluster.local", service.name, namespace);
+        let addresses = k8s::endpoints::get_addresses(ep, service.port);
+        let tls = match service.scheme {
+            ServiceScheme::Https => {
+                Some(Self::load_upstream_tls(client.clone(), &namespace, service).await?)
+            }
+            ServiceScheme::Http => None,
+        };
+        let load_balancer = ServiceLoadBalancer::new(&sni, service, addresses, tls)?;
+
+        let lb = load_balancer.clone();
+        k8s::endpoints::watch(
+            client,
+            namespace,
+            service.name.clone(),
+            service.port,
+            notify,
+            move |addresses| match lb.update(addresses) {
+                Ok(_) => debug!("Load balancer updated with new endpoint addresses"),
+                Err(e) => error!("Unable to update load balancer with new endpoints: {}", e),
+            },
+        );
+
+        Ok(load_balancer)
     }
 }
 
 #[async_trait]
 impl ProxyHttp for Gateway {
-    type CTX = ();
+    type CTX = Context;
+
+    fn new_ctx(&self) -> Self::CTX {
+        Context::default()
+    }
+
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<bool> {
+        let req = session.req_header();
+        let host = match req.headers.get(HOST) {
+            Some(value) => value
+                .to_str()
+                .map_err(|e| {
+                    pingora::Error::because(
+                        pingora::ErrorType::InvalidHTTPHeader,
+                        "Invalid Host header",
+                        e,
+                    )
+                })?
+                .to_string(),
+            None => req
+                .uri
+                .authority()
+                .map(|authority| match authority.port_u16() {
+                    Some(port) => format!("{}:{}", authority.host(), port),
+                    None => authority.host().to_string(),
+                })
+                .ok_or(pingora::Error::create(
+                    pingora::ErrorType::InvalidHTTPHeader,
+                    pingora::ErrorSource::Upstream,
+                    Some("No HTTP Host header or authority present in request".into()),
+                    None,
+                ))?,
+        };
+        let name = host::normalize(&host, self.port).ok_or(pingora::Error::explain(
+            pingora::ErrorType::InvalidHTTPHeader,
+            "Invalid Host header",
+        ))?;
+        let secure = self.certificates.is_some();
+
+        if let Some(redirect) = &self.redirect {
+            redirect.respond(session, &host, secure).await?;
+            return Ok(true);
+        }
 
-    fn new_ctx(&self) -> Self::CTX {}
+        let Some((route, captures)) = self.find_route(session, &name) else {
+            return Ok(false);
+        };
+        if let Action::Redirect(redirect) = &route.action {
+            redirect.respond(session, &host, secure).await?;
+            return Ok(true);
+        }
+        if session.is_upgrade_req() && !route.upgrade.allowed {
+            return Err(pingora::Error::explain(
+                pingora::ErrorType::HTTPStatus(StatusCode::FORBIDDEN.as_u16()),
+                "Protocol upgrade not allowed for route",
+            ));
+        }
+
+        if !route.middleware.is_empty() {
+            let mut variables = Variables::new(
+                session.req_header(),
+                session::client_ip(session),
+                &route.name,
+                captures,
+            );
+            if let Some(response) = route
+                .middleware
+                .request_filter(session.req_header(), &mut variables)?
+            {
+                session
+                    .write_response_header(Box::new(response), true)
+                    .await?;
+                return Ok(true);
+            }
+            ctx.middleware = Some((route.middleware.clone(), variables));
+        }
+        ctx.route = Some(route);
+        Ok(false)
+    }
 
     async fn upstream_peer(
         &self,
         session: &mut Session,
         ctx: &mut Self::CTX,
     ) -> pingora::Result<Box<HttpPeer>> {
-        let host = session
-            .req_header()
-            .headers
-            .get(HOST)
-            .ok_or(pingora::Error::create(
-                pingora::ErrorType::InvalidHTTPHeader,
-                pingora::ErrorSource::Upstream,
-                Some("No HTTP Host header present in request".into()),
-                None,
-            ))?
-            .to_str()
-            .map_err(|e| {
-                pingora::Error::because(
-                    pingora::ErrorType::InvalidHTTPHeader,
-                    "Invalid Host header",
-                    e,
-                )
-            })?;
+        let Some(Action::Forward(split)) = ctx.route.as_ref().map(|route| &route.action) else {
+            return Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(
+                StatusCode::NOT_FOUND.as_u16(),
+            )));
+        };
+
+        let retry = ctx.route.as_ref().and_then(|route| route.retry.clone());
+        ctx.attempts += 1;
+        if let Some(policy) = retry.as_ref().filter(|_| ctx.attempts > 1) {
+            tokio::time::sleep(policy.backoff(ctx.attempts)).await;
+        }
+
+        let lb = match ctx.load_balancer.clone() {
+            Some(lb) => lb,
+            None => {
+                let service = split.select(session.req_header()).ok_or_else(|| {
+                    pingora::Error::explain(
+                        pingora::ErrorType::HTTPStatus(StatusCode::SERVICE_UNAVAILABLE.as_u16()),
+                        "No weighted service available for route",
+                    )
+                })?;
+                ctx.variant = split
+                    .variant_header()
+                    .map(|header| (header.to_string(), service.name.clone()));
+                ctx.load_balancer = Some(service.load_balancer.clone());
+                service.load_balancer.clone()
+            }
+        };
+        let timeouts = lb.timeouts().or(&self.timeouts);
+        if session.is_upgrade_req() {
+            let idle_timeout = ctx
+                .route
+                .as_ref()
+                .map(|route| route.upgrade.idle_timeout)
+                .unwrap_or_default();
+            let mut peer = lb.upstream_peer(session, &mut ctx.upstream).await?;
+            timeouts.apply(&mut peer);
+            peer.options.read_timeout = Some(idle_timeout);
+            peer.options.write_timeout = Some(idle_timeout);
+            peer.options.idle_timeout = None;
+            session.set_write_timeout(idle_timeout);
+            return Ok(peer);
+        }
+        if ctx.deadline.is_none() {
+            ctx.deadline = timeouts
+                .total()
+                .into_iter()
+                .chain(grpc::timeout(session.req_header()))
+                .min()
+                .map(|timeout| Instant::now() + timeout);
+        }
+        let remaining = ctx
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining.is_some_and(|remaining| remaining.is_zero()) {
+            return Err(deadline_exceeded(pingora::ErrorType::ConnectTimedout));
+        }
+
+        let mut peer = lb.upstream_peer(session, &mut ctx.upstream).await?;
+        timeouts.apply(&mut peer);
+        if let Some(timeout) = retry.and_then(|policy| policy.per_try_timeout) {
+            peer.options.total_connection_timeout = Some(timeout);
+            peer.options.read_timeout = Some(timeout);
+        }
+        if let Some(remaining) = remaining {
+            let options = &mut peer.options;
+            let clamp =
+                |timeout: Option<Duration>| Some(timeout.map_or(remaining, |t| t.min(remaining)));
+            options.total_connection_timeout = clamp(options.total_connection_timeout);
+            options.read_timeout = clamp(options.read_timeout);
+            options.write_timeout = clamp(options.write_timeout);
+        }
+        Ok(peer)
+    }
+
+    fn fail_to_connect(
+        &self,
+        session: &mut Session,
+        peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        e: Box<pingora::Error>,
+    ) -> Box<pingora::Error> {
+        let mut e = match ctx.load_balancer.clone() {
+            Some(lb) => lb.fail_to_connect(session, peer, &mut ctx.upstream, e),
+            None => e,
+        };
+        if let Some(policy) = ctx.route.as_ref().and_then(|route| route.retry.as_ref()) {
+            e.set_retry(
+                policy.on_connect_failure && policy.allows(session.req_header(), ctx.attempts),
+            );
+        }
+        e
+    }
+
+    fn error_while_proxy(
+        &self,
+        peer: &HttpPeer,
+        session: &mut Session,
+        e: Box<pingora::Error>,
+        ctx: &mut Self::CTX,
+        client_reused: bool,
+    ) -> Box<pingora::Error> {
+        let mut e = e.more_context(format!("Peer: {}", peer));
+        let replayable =
+            !session.as_ref().retry_buffer_truncated() && session.response_written().is_none();
+        e.retry.decide_reuse(client_reused && replayable);
+        if let Some(policy) = ctx.route.as_ref().and_then(|route| route.retry.as_ref()) {
+            if replayable
+                && policy.retry_error(session.req_header(), e.etype())
+                && policy.allows(session.req_header(), ctx.attempts)
+            {
+                e.set_retry(true);
+            }
+        }
+        e
+    }
+
+    async fn fail_to_proxy(
+        &self,
+        session: &mut Session,
+        e: &pingora::Error,
+        _ctx: &mut Self::CTX,
+    ) -> u16 {
+        let timed_out = e.esource() == &pingora::ErrorSource::Upstream
+            && matches!(
+                e.etype(),
+                pingora::ErrorType::ConnectTimedout
+                    | pingora::ErrorType::TLSHandshakeTimedout
+                    | pingora::ErrorType::ReadTimedout
+                    | pingora::ErrorType::WriteTimedout
+            );
+        let code = match timed_out {
+            true => StatusCode::GATEWAY_TIMEOUT.as_u16(),
+            false => error_code(e),
+        };
+        // Headers already went out, e.g. when the deadline passed mid-body.
+        if code == 0 || session.response_written().is_some() {
+            return code;
+        }
+
+        let result = if grpc::is_grpc(session.req_header()) {
+            grpc::respond_error(session, grpc::status(code, timed_out)).await
+        } else if timed_out {
+            respond_timeout(session).await
+        } else {
+            session.as_mut().respond_error(code).await;
+            Ok(())
+        };
+        if let Err(e) = result {
+            error!("Unable to write error response: {}", e);
+        }
+        code
+    }
+
+    async fn request_body_filter(
+        &self,
+        _session: &mut Session,
+        _body: &mut Option<Bytes>,
+        _end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        match ctx.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err(deadline_exceeded(pingora::ErrorType::WriteTimedout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn response_body_filter(
+        &self,
+        _session: &mut Session,
+        _body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<Option<Duration>> {
+        match ctx.deadline {
+            Some(deadline) if !end_of_stream && Instant::now() >= deadline => {
+                Err(deadline_exceeded(pingora::ErrorType::ReadTimedout))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn upstream_request_filter(
+        &self,
+        _session: &mut Session,
+        upstream_request: &mut RequestHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        if let Some((chain, variables)) = &ctx.middleware {
+            chain.upstream_request(upstream_request, variables)?;
+        }
+        Ok(())
+    }
+
+    async fn response_filter(
+        &self,
+        session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        if let Some(lb) = ctx.load_balancer.clone() {
+            lb.response_filter(session, upstream_response, &mut ctx.upstream)
+                .await?;
+        }
+        let status = upstream_response.status.as_u16();
+        if let Some(policy) = ctx.route.as_ref().and_then(|route| route.retry.as_ref()) {
+            if policy.retry_status(status) && policy.allows(session.req_header(), ctx.attempts) {
+                return Err(pingora::Error::explain(
+                    pingora::ErrorType::HTTPStatus(status),
+                    "Retrying upstream response status",
+                ));
+            }
+        }
+        if status == StatusCode::SWITCHING_PROTOCOLS.as_u16() {
+            ctx.upgrade = ctx.route.as_ref().map(|route| route.upgrade.track());
+        }
+        if let Some((header, variant)) = ctx.variant.take() {
+            upstream_response.insert_header(header, variant)?;
+        }
+        if let Some((chain, variables)) = &ctx.middleware {
+            chain.response(upstream_response, variables)?;
+        }
+        Ok(())
+    }
+}
+
+async fn respond_timeout(session: &mut Session) -> pingora::Result<()> {
+    let mut response = ResponseHeader::build(StatusCode::GATEWAY_TIMEOUT, Some(3))?;
+    response.insert_header("content-type", "text/plain")?;
+    response.insert_header("content-length", GATEWAY_TIMEOUT_BODY.len().to_string())?;
+    session
+        .write_response_header(Box::new(response), false)
+        .await?;
+    session
+        .write_response_body(
+            Some(Bytes::from_static(GATEWAY_TIMEOUT_BODY.as_bytes())),
+            true,
+        )
+        .await
+}
+
+fn deadline_exceeded(etype: pingora::ErrorType) -> Box<pingora::Error> {
+    pingora::Error::explain(etype, "Total request timeout exceeded").into_up()
+}
+
+fn error_code(e: &pingora::Error) -> u16 {
+    match (e.etype(), e.esource()) {
+        (pingora::ErrorType::HTTPStatus(code), _) => *code,
+        (_, pingora::ErrorSource::Upstream) => 502,
+        (
+            pingora::ErrorType::WriteError
+            | pingora::ErrorType::ReadError
+            | pingora::ErrorType::ConnectionClosed,
+            pingora::ErrorSource::Downstream,
+        ) => 0,
+        (_, pingora::ErrorSource::Downstream) => 400,
+        _ => 500,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Action, Gateway, HostTable, ManagedRoute, Rank, Redirect, Route, SharedGateway,
+        TrafficSplit, WeightedService,
+    };
+    use crate::k8s::endpoints::Address;
+    use crate::k8s::testing;
+    use crate::load_balancer::{ServiceLoadBalancer, Timeouts};
+    use crate::matcher::Matcher;
+    use arc_swap::ArcSwapOption;
+    use crds::{IngressRoute, IngressRouteService};
+    use dashmap::DashMap;
+    use futures_util::FutureExt;
+    use kube::Resource;
+    use pingora::proxy::http_proxy_service;
+    use pingora::server::configuration::ServerConf;
+    use pingora::services::Service;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::Notify;
 
-        if let Some(lb) = self.route_table.get(host) {
-            return lb.value().upstream_peer(session, ctx).await;
+    const TOTAL_TIMEOUT: Duration = Duration::from_millis(300);
+
+    // Serves `response` to every connection in pieces, pausing between them.
+    async fn upstream(response: Vec<&'static [u8]>, pause: Duration) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let response = response.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    for chunk in response {
+                        tokio::time::sleep(pause).await;
+                        if stream.write_all(chunk).await.is_err() {
+                            return;
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn proxy(upstream_port: u16) -> u16 {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let timeouts = Timeouts {
+            total_millis: Some(TOTAL_TIMEOUT.as_millis() as u64),
+            ..Default::default()
+        };
+        let gateway = Gateway::new(port, None, None, timeouts);
+        let load_balancer = ServiceLoadBalancer::new(
+            "upstream",
+            &IngressRouteService::default(),
+            vec![Address {
+                ip: "127.0.0.1".into(),
+                port: upstream_port,
+                pod: None,
+            }],
+            None,
+        )
+        .unwrap();
+        let split = TrafficSplit::new(
+            vec![WeightedService {
+                name: "upstream".into(),
+                weight: 1,
+                load_balancer,
+            }],
+            None,
+        )
+        .unwrap();
+        let mut table = HostTable::default();
+        table.routes.insert(
+            "example.com".into(),
+            vec![Route {
+                name: "default/test".into(),
+                matches: String::new(),
+                priority: 0,
+                matcher: Arc::new(Matcher::Any),
+                action: Action::Forward(split),
+                middleware: Default::default(),
+                retry: None,
+                upgrade: Default::default(),
+            }],
+        );
+        gateway.route_table.store(Arc::new(table));
+
+        let conf = Arc::new(ServerConf::default());
+        let mut service = http_proxy_service(&conf, SharedGateway::new(gateway));
+        service.add_tcp(&format!("127.0.0.1:{}", port));
+        let (_shutdown_tx, shutdown) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            let _shutdown_tx = _shutdown_tx;
+            service.start_service(None, shutdown).await;
+        });
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
         }
+        port
+    }
+
+    async fn get(port: u16) -> (Vec<u8>, Duration) {
+        let started = Instant::now();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        let _ = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+            .await
+            .unwrap();
+        (response, started.elapsed())
+    }
+
+    #[tokio::test]
+    async fn stalled_upstream_gets_gateway_timeout() {
+        let port = proxy(upstream(vec![], Duration::ZERO).await).await;
+        let (response, elapsed) = get(port).await;
+        assert!(
+            response.starts_with(b"HTTP/1.1 504"),
+            "{}",
+            String::from_utf8_lossy(&response)
+        );
+        assert!(elapsed < TOTAL_TIMEOUT * 3, "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn slow_body_is_cut_off_at_the_deadline() {
+        let mut response: Vec<&'static [u8]> =
+            vec![b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"];
+        response.extend(std::iter::repeat_n(b"1\r\na\r\n".as_slice(), 20));
+        response.push(b"0\r\n\r\n");
+        let port = proxy(upstream(response, Duration::from_millis(50)).await).await;
+        let (response, elapsed) = get(port).await;
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(!response.ends_with("0\r\n\r\n"), "{}", response);
+        assert!(elapsed < TOTAL_TIMEOUT * 3, "{:?}", elapsed);
+    }
+
+    fn claim(gateway: &Gateway, name: &str, created: &str, hosts: &[&str]) -> IngressRoute {
+        let route: IngressRoute = testing::resource(
+            "IngressRoute",
+            name,
+            created,
+            serde_json::json!({"entrypoint": "web", "route": {"hosts": hosts, "rules": []}}),
+        );
+        let route_name = format!("default/{}", name);
+        gateway.managed_objects.insert(
+            name.to_string(),
+            ManagedRoute {
+                reference: route.object_ref(&()),
+                rank: Rank::new(&route, &route_name),
+                hosts: hosts.iter().map(|h| h.to_string()).collect(),
+                routes: vec![Route {
+                    name: route_name,
+                    matches: String::new(),
+                    priority: 0,
+                    matcher: Arc::new(Matcher::Any),
+                    action: Action::Redirect(Redirect::to_entry_point(true, 443)),
+                    middleware: Default::default(),
+                    retry: None,
+                    upgrade: Default::default(),
+                }],
+                certificate: Arc::new(ArcSwapOption::empty()),
+                generation: Some(1),
+                condition: None,
+                services: Vec::new(),
+                watches: Vec::new(),
+            },
+        );
+        route
+    }
+
+    fn served(table: &HostTable, host: &str) -> Option<String> {
+        table.routes.get(host).map(|routes| routes[0].name.clone())
+    }
+
+    #[test]
+    fn host_changes_are_published_as_one_table() {
+        let gateway = Gateway::new(80, None, None, Timeouts::default());
+        claim(
+            &gateway,
+            "a",
+            "2024-01-02T00:00:00Z",
+            &["a.example.com", "t-(.+).example.com"],
+        );
+        claim(&gateway, "b", "2024-01-01T00:00:00Z", &["b.example.com"]);
+        gateway.resolve_hosts(&["a.example.com".into(), "t-(.+).example.com".into()]);
+        gateway.resolve_hosts(&["b.example.com".into()]);
+        let before = gateway.route_table.load_full();
+
+        claim(&gateway, "a", "2024-01-02T00:00:00Z", &["b.example.com"]);
+        gateway.resolve_hosts(&[
+            "a.example.com".into(),
+            "t-(.+).example.com".into(),
+            "b.example.com".into(),
+        ]);
+        let after = gateway.route_table.load_full();
+
+        assert_eq!(
+            served(&before, "a.example.com").as_deref(),
+            Some("default/a")
+        );
+        assert_eq!(before.regex_hosts.len(), 1);
+        assert_eq!(served(&after, "a.example.com"), None);
+        assert!(after.regex_hosts.is_empty());
+        assert_eq!(
+            served(&after, "b.example.com").as_deref(),
+            Some("default/b")
+        );
+        assert_eq!(
+            gateway.conflicts.get("b.example.com").unwrap().losers,
+            ["default/a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_route_releases_its_hosts() {
+        let gateway = SharedGateway::new(Gateway::new(80, None, None, Timeouts::default()));
+        let route_tables = Arc::new(DashMap::new());
+        route_tables.insert("web".to_string(), gateway.clone());
+        let hosts = [
+            "a.example.com".to_string(),
+            "t-(.+).example.com".to_string(),
+        ];
+        let old = claim(
+            &gateway.0,
+            "old",
+            "2024-01-01T00:00:00Z",
+            &["a.example.com", "t-(.+).example.com"],
+        );
+        claim(
+            &gateway.0,
+            "new",
+            "2024-01-02T00:00:00Z",
+            &["a.example.com"],
+        );
+        gateway.0.resolve_hosts(&hosts);
+
+        Gateway::delete_routes(route_tables)(testing::client(), old)
+            .await
+            .unwrap();
+
+        let table = gateway.0.route_table.load();
+        assert!(!gateway.0.managed_objects.contains_key("old"));
+        assert_eq!(
+            served(&table, "a.example.com").as_deref(),
+            Some("default/new")
+        );
+        assert!(table.regex_hosts.is_empty());
+        assert!(!table.routes.contains_key("t-(.+).example.com"));
+        assert!(gateway.0.conflicts.get("a.example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_conflict_report_is_retried() {
+        let gateway = Gateway::new(80, None, None, Timeouts::default());
+        let hosts = ["a.example.com".to_string()];
+        claim(&gateway, "old", "2024-01-01T00:00:00Z", &["a.example.com"]);
+        claim(&gateway, "new", "2024-01-02T00:00:00Z", &["a.example.com"]);
+        gateway.resolve_hosts(&hosts);
+
+        gateway.report_conflicts(testing::client(), &hosts).await;
+        for route in ["old", "new"] {
+            assert!(gateway
+                .managed_objects
+                .get(route)
+                .unwrap()
+                .condition
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn load_balancer_is_kept_unless_the_service_changes() {
+        let service = |port, weight| IngressRouteService {
+            name: "api".into(),
+            port,
+            weight,
+            ..Default::default()
+        };
+        let key = Gateway::service_key("default", &service(80, Some(1)));
+        assert_eq!(Gateway::service_key("default", &service(80, Some(9))), key);
+        assert_ne!(Gateway::service_key("default", &service(81, Some(1))), key);
+        assert_ne!(Gateway::service_key("other", &service(80, Some(1))), key);
 
-        Err(pingora::Error::new(pingora::ErrorType::HTTPStatus(
-            StatusCode::NOT_FOUND.as_u16(),
-        )))
+        let gateway = Gateway::new(80, None, None, Timeouts::default());
+        claim(&gateway, "a", "2024-01-01T00:00:00Z", &["a.example.com"]);
+        let (shared, replaced) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let mut object = gateway.managed_objects.get_mut("a").unwrap();
+        object.watches = vec![shared.clone(), replaced.clone()];
+        object.stop_watches(std::slice::from_ref(&shared));
+        assert!(shared.notified().now_or_never().is_none());
+        assert!(replaced.notified().now_or_never().is_some());
     }
 }
@@ -0,0 +1,227 @@
+use anyhow::anyhow;
+use crds::IngressRouteRedirect;
+use pingora::http::{RequestHeader, ResponseHeader, StatusCode};
+use pingora::prelude::Session;
+
+#[derive(Clone)]
+pub struct Redirect {
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub path: Option<String>,
+    pub status: StatusCode,
+}
+
+impl TryFrom<&IngressRouteRedirect> for Redirect {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: &IngressRouteRedirect) -> Result<Self, Self::Error> {
+        let status = match spec.status_code.unwrap_or(302) {
+            code @ (301 | 302 | 307 | 308) => StatusCode::from_u16(code)?,
+            code => return Err(anyhow!("unsupported redirect status code {}", code)),
+        };
+        let scheme = spec.scheme.as_ref().map(|s| s.to_lowercase());
+        if let Some(scheme) = scheme
+            .as_deref()
+            .filter(|s| !matches!(*s, "http" | "https"))
+        {
+            return Err(anyhow!("unsupported redirect scheme '{}'", scheme));
+        }
+        Ok(Self {
+            scheme,
+            host: spec.host.clone(),
+            port: spec.port,
+            path: spec.path.clone(),
+            status,
+        })
+    }
+}
+
+impl Redirect {
+    pub fn to_entry_point(secure: bool, port: u16) -> Self {
+        Self {
+            scheme: Some(if secure { "https" } else { "http" }.to_string()),
+            host: None,
+            port: Some(port),
+            path: None,
+            status: StatusCode::PERMANENT_REDIRECT,
+        }
+    }
+
+    pub fn location(&self, req: &RequestHeader, host: &str, secure: bool) -> String {
+        let (request_host, request_port) = match host.rsplit_once(':') {
+            Some((h, _)) if !h.ends_with(']') && h.contains(':') => (host, None),
+            Some((h, p)) => (h, p.parse::<u16>().ok()),
+            None => (host, None),
+        };
+        let scheme = self
+            .scheme
+            .as_deref()
+            .unwrap_or(if secure { "https" } else { "http" });
+        let port = match (self.port, &self.scheme, &self.host) {
+            (Some(port), _, _) => Some(port),
+            (None, None, None) => request_port,
+            _ => None,
+        }
+        .filter(|port| !matches!((scheme, port), ("http", 80) | ("https", 443)));
+
+        let mut location = format!(
+            "{}://{}",
+            scheme,
+            self.host.as_deref().unwrap_or(request_host)
+        );
+        if let Some(port) = port {
+            location.push_str(&format!(":{}", port));
+        }
+        location.push_str(self.path.as_deref().unwrap_or(req.uri.path()));
+        if let Some(query) = req.uri.query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        location
+    }
+
+    pub async fn respond(
+        &self,
+        session: &mut Session,
+        host: &str,
+        secure: bool,
+    ) -> pingora::Result<()> {
+        let location = self.location(session.req_header(), host, secure);
+        let mut response = ResponseHeader::build(self.status, Some(2))?;
+        response.insert_header("location", location)?;
+        response.insert_header("content-length", "0")?;
+        session
+            .write_response_header(Box::new(response), true)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Redirect;
+    use crds::IngressRouteRedirect;
+    use pingora::http::{RequestHeader, StatusCode};
+
+    fn request(uri: &str) -> RequestHeader {
+        RequestHeader::build("GET", uri.as_bytes(), None).unwrap()
+    }
+
+    fn redirect(spec: IngressRouteRedirect) -> Redirect {
+        Redirect::try_from(&spec).unwrap()
+    }
+
+    #[test]
+    fn keeps_request_host_port_path_and_query_by_default() {
+        let r = redirect(IngressRouteRedirect::default());
+        assert_eq!(r.status, StatusCode::FOUND);
+        assert_eq!(
+            r.location(&request("/a/b?x=1&y"), "example.com:8080", false),
+            "http://example.com:8080/a/b?x=1&y"
+        );
+        assert_eq!(
+            r.location(&request("/"), "example.com", true),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn omits_default_ports() {
+        let r = redirect(IngressRouteRedirect::default());
+        assert_eq!(
+            r.location(&request("/"), "example.com:80", false),
+            "http://example.com/"
+        );
+        assert_eq!(
+            r.location(&request("/"), "example.com:443", true),
+            "https://example.com/"
+        );
+        let https = redirect(IngressRouteRedirect {
+            scheme: Some("HTTPS".into()),
+            port: Some(443),
+            ..Default::default()
+        });
+        assert_eq!(
+            https.location(&request("/p"), "example.com:8080", false),
+            "https://example.com/p"
+        );
+    }
+
+    #[test]
+    fn changing_scheme_or_host_drops_request_port() {
+        let scheme = redirect(IngressRouteRedirect {
+            scheme: Some("https".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            scheme.location(&request("/p"), "example.com:8080", false),
+            "https://example.com/p"
+        );
+        let host = redirect(IngressRouteRedirect {
+            host: Some("other.com".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            host.location(&request("/p?q"), "example.com:8080", false),
+            "http://other.com/p?q"
+        );
+    }
+
+    #[test]
+    fn overrides_port_and_path() {
+        let r = redirect(IngressRouteRedirect {
+            port: Some(8443),
+            path: Some("/new".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            r.location(&request("/old?q=1"), "example.com", true),
+            "https://example.com:8443/new?q=1"
+        );
+    }
+
+    #[test]
+    fn handles_ipv6_hosts() {
+        let r = redirect(IngressRouteRedirect::default());
+        assert_eq!(
+            r.location(&request("/"), "[::1]:8080", false),
+            "http://[::1]:8080/"
+        );
+        assert_eq!(r.location(&request("/"), "[::1]", false), "http://[::1]/");
+        assert_eq!(r.location(&request("/"), "::1", false), "http://::1/");
+    }
+
+    #[test]
+    fn entry_point_redirect_uses_target_scheme_and_port() {
+        let r = Redirect::to_entry_point(true, 6443);
+        assert_eq!(r.status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            r.location(&request("/a?b"), "example.com:6190", false),
+            "https://example.com:6443/a?b"
+        );
+        assert_eq!(
+            Redirect::to_entry_point(true, 443).location(&request("/"), "example.com:80", false),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_status_and_scheme() {
+        let status = IngressRouteRedirect {
+            status_code: Some(303),
+            ..Default::default()
+        };
+        assert_eq!(
+            Redirect::try_from(&status).err().unwrap().to_string(),
+            "unsupported redirect status code 303"
+        );
+        let scheme = IngressRouteRedirect {
+            scheme: Some("ftp".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Redirect::try_from(&scheme).err().unwrap().to_string(),
+            "unsupported redirect scheme 'ftp'"
+        );
+    }
+}
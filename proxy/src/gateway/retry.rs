@@ -0,0 +1,188 @@
+use anyhow::anyhow;
+use crds::IngressRouteRetry;
+use pingora::http::{Method, RequestHeader};
+use pingora::ErrorType;
+use std::time::Duration;
+
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(25);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_millis(250);
+
+#[derive(Clone)]
+pub struct RetryPolicy {
+    pub attempts: usize,
+    pub on_connect_failure: bool,
+    pub on_read_write_failure: bool,
+    pub status_codes: Vec<u16>,
+    pub idempotent_only: bool,
+    pub per_try_timeout: Option<Duration>,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl TryFrom<&IngressRouteRetry> for RetryPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: &IngressRouteRetry) -> Result<Self, Self::Error> {
+        if spec.attempts == 0 {
+            return Err(anyhow!("attempts must be greater than 0"));
+        }
+        Ok(Self {
+            attempts: spec.attempts as usize,
+            on_connect_failure: spec.on_connect_failure.unwrap_or(true),
+            on_read_write_failure: spec.on_read_write_failure.unwrap_or(false),
+            status_codes: spec.status_codes.clone(),
+            idempotent_only: spec.idempotent_only.unwrap_or(true),
+            per_try_timeout: spec.per_try_timeout_millis.map(Duration::from_millis),
+            backoff: spec
+                .backoff_millis
+                .map_or(DEFAULT_BACKOFF, Duration::from_millis),
+            max_backoff: spec
+                .max_backoff_millis
+                .map_or(DEFAULT_MAX_BACKOFF, Duration::from_millis),
+        })
+    }
+}
+
+impl RetryPolicy {
+    pub fn allows(&self, req: &RequestHeader, attempt: usize) -> bool {
+        attempt < self.attempts && (!self.idempotent_only || is_idempotent(&req.method))
+    }
+
+    pub fn retry_status(&self, status: u16) -> bool {
+        self.status_codes.contains(&status)
+    }
+
+    pub fn retry_error(&self, req: &RequestHeader, etype: &ErrorType) -> bool {
+        match etype {
+            ErrorType::HTTPStatus(status) => self.retry_status(*status),
+            ErrorType::ConnectionClosed => self.on_connect_failure,
+            // The upstream may already have acted on the request.
+            ErrorType::ReadError
+            | ErrorType::ReadTimedout
+            | ErrorType::WriteError
+            | ErrorType::WriteTimedout => self.on_read_write_failure && is_idempotent(&req.method),
+            _ => false,
+        }
+    }
+
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(2).min(16) as u32;
+        self.backoff
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_backoff)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::TRACE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use crds::IngressRouteRetry;
+    use pingora::http::RequestHeader;
+    use pingora::ErrorType;
+    use std::time::Duration;
+
+    fn policy(spec: IngressRouteRetry) -> RetryPolicy {
+        RetryPolicy::try_from(&spec).unwrap()
+    }
+
+    fn request(method: &str) -> RequestHeader {
+        RequestHeader::build(method, b"/", None).unwrap()
+    }
+
+    fn policy_with_idempotent_only(idempotent_only: bool) -> RetryPolicy {
+        policy(IngressRouteRetry {
+            attempts: 2,
+            idempotent_only: Some(idempotent_only),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = policy(IngressRouteRetry {
+            attempts: 10,
+            backoff_millis: Some(10),
+            max_backoff_millis: Some(50),
+            ..Default::default()
+        });
+        let backoffs: Vec<_> = (1..=6).map(|attempt| policy.backoff(attempt)).collect();
+        assert_eq!(
+            backoffs,
+            [10, 10, 20, 40, 50, 50].map(Duration::from_millis)
+        );
+        assert_eq!(policy.backoff(usize::MAX), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn backoff_defaults() {
+        let policy = policy(IngressRouteRetry {
+            attempts: 3,
+            ..Default::default()
+        });
+        assert_eq!(policy.backoff(2), Duration::from_millis(25));
+        assert_eq!(policy.backoff(100), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn allows_respects_attempts_and_idempotency() {
+        let policy = policy(IngressRouteRetry {
+            attempts: 2,
+            ..Default::default()
+        });
+        assert!(policy.allows(&request("GET"), 1));
+        assert!(!policy.allows(&request("GET"), 2));
+        assert!(!policy.allows(&request("POST"), 1));
+        let any_method = policy_with_idempotent_only(false);
+        assert!(any_method.allows(&request("POST"), 1));
+    }
+
+    #[test]
+    fn read_write_failures_need_their_own_option_and_an_idempotent_method() {
+        let errors = [
+            ErrorType::ReadError,
+            ErrorType::ReadTimedout,
+            ErrorType::WriteError,
+            ErrorType::WriteTimedout,
+        ];
+        let default = policy_with_idempotent_only(false);
+        assert!(default.retry_error(&request("GET"), &ErrorType::ConnectionClosed));
+        for etype in &errors {
+            assert!(!default.retry_error(&request("GET"), etype));
+        }
+
+        let enabled = policy(IngressRouteRetry {
+            attempts: 2,
+            idempotent_only: Some(false),
+            on_read_write_failure: Some(true),
+            ..Default::default()
+        });
+        for etype in &errors {
+            assert!(enabled.retry_error(&request("PUT"), etype));
+            assert!(!enabled.retry_error(&request("POST"), etype));
+        }
+    }
+
+    #[test]
+    fn retries_configured_status_codes() {
+        let policy = policy(IngressRouteRetry {
+            attempts: 2,
+            status_codes: vec![502, 503],
+            ..Default::default()
+        });
+        assert!(policy.retry_error(&request("GET"), &ErrorType::HTTPStatus(503)));
+        assert!(!policy.retry_error(&request("GET"), &ErrorType::HTTPStatus(500)));
+        assert!(!policy.retry_error(&request("GET"), &ErrorType::ConnectTimedout));
+    }
+
+    #[test]
+    fn rejects_zero_attempts() {
+        assert!(RetryPolicy::try_from(&IngressRouteRetry::default()).is_err());
+    }
+}
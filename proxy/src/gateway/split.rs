@@ -0,0 +1,282 @@
+use crate::load_balancer::ServiceLoadBalancer;
+use crate::session;
+use anyhow::anyhow;
+use crds::{IngressRouteCanary, IngressRouteCanarySelector};
+use pingora::http::RequestHeader;
+use rand::Rng;
+
+const DEFAULT_VARIANT_HEADER: &str = "x-ferrix-variant";
+
+#[derive(Clone)]
+pub struct WeightedService {
+    pub name: String,
+    pub weight: u32,
+    pub load_balancer: ServiceLoadBalancer,
+}
+
+#[derive(Clone)]
+pub struct TrafficSplit {
+    services: Vec<WeightedService>,
+    total_weight: u64,
+    canary: Option<Canary>,
+}
+
+#[derive(Clone)]
+struct Canary {
+    service: usize,
+    headers: Vec<Selector>,
+    cookies: Vec<Selector>,
+    response_header: String,
+}
+
+#[derive(Clone)]
+struct Selector {
+    name: String,
+    value: Option<String>,
+}
+
+impl From<&IngressRouteCanarySelector> for Selector {
+    fn from(selector: &IngressRouteCanarySelector) -> Self {
+        Self {
+            name: selector.name.clone(),
+            value: selector.value.clone(),
+        }
+    }
+}
+
+impl Selector {
+    fn matches(&self, value: Option<&str>) -> bool {
+        match (value, &self.value) {
+            (Some(value), Some(expected)) => value == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl Canary {
+    fn matches(&self, req: &RequestHeader) -> bool {
+        let header = self.headers.iter().any(|selector| {
+            req.headers
+                .get_all(selector.name.as_str())
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .any(|v| selector.matches(Some(v)))
+        });
+        header
+            || self
+                .cookies
+                .iter()
+                .any(|selector| selector.matches(session::get_cookie(req, &selector.name)))
+    }
+}
+
+impl TrafficSplit {
+    pub fn new(
+        services: Vec<WeightedService>,
+        canary: Option<&IngressRouteCanary>,
+    ) -> Result<Self, anyhow::Error> {
+        let canary = canary
+            .map(|canary| {
+                let service = services
+                    .iter()
+                    .position(|s| s.name == canary.service)
+                    .ok_or_else(|| {
+                        anyhow!("canary service '{}' is not a rule service", canary.service)
+                    })?;
+                if canary.headers.is_empty() && canary.cookies.is_empty() {
+                    return Err(anyhow!(
+                        "canary for service '{}' has no header or cookie selectors",
+                        canary.service
+                    ));
+                }
+                Ok(Canary {
+                    service,
+                    headers: canary.headers.iter().map(Selector::from).collect(),
+                    cookies: canary.cookies.iter().map(Selector::from).collect(),
+                    response_header: canary
+                        .response_header
+                        .clone()
+                        .unwrap_or(DEFAULT_VARIANT_HEADER.to_string()),
+                })
+            })
+            .transpose()?;
+
+        let total_weight = services.iter().map(|s| s.weight as u64).sum();
+        Ok(Self {
+            services,
+            total_weight,
+            canary,
+        })
+    }
+
+    pub fn services(&self) -> &[WeightedService] {
+        &self.services
+    }
+
+    pub fn canary(&self) -> Option<&str> {
+        self.canary
+            .as_ref()
+            .map(|canary| self.services[canary.service].name.as_str())
+    }
+
+    pub fn variant_header(&self) -> Option<&str> {
+        match &self.canary {
+            Some(canary) => Some(canary.response_header.as_str()),
+            None if self.services.len() > 1 => Some(DEFAULT_VARIANT_HEADER),
+            None => None,
+        }
+    }
+
+    pub fn select(&self, req: &RequestHeader) -> Option<&WeightedService> {
+        if let Some(canary) = self.canary.as_ref().filter(|c| c.matches(req)) {
+            return self.services.get(canary.service);
+        }
+        if self.services.len() == 1 {
+            return self.services.first();
+        }
+        if self.total_weight == 0 {
+            return None;
+        }
+
+        let mut point = rand::thread_rng().gen_range(0..self.total_weight);
+        for service in &self.services {
+            let weight = service.weight as u64;
+            if point < weight {
+                return Some(service);
+            }
+            point -= weight;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrafficSplit, WeightedService};
+    use crate::load_balancer::ServiceLoadBalancer;
+    use crds::{IngressRouteCanary, IngressRouteCanarySelector, IngressRouteService};
+    use pingora::http::RequestHeader;
+
+    fn services(weights: &[(&str, u32)]) -> Vec<WeightedService> {
+        weights
+            .iter()
+            .map(|(name, weight)| WeightedService {
+                name: name.to_string(),
+                weight: *weight,
+                load_balancer: ServiceLoadBalancer::new(
+                    name,
+                    &IngressRouteService::default(),
+                    Vec::new(),
+                    None,
+                )
+                .unwrap(),
+            })
+            .collect()
+    }
+
+    fn split(weights: &[(&str, u32)], canary: Option<&IngressRouteCanary>) -> TrafficSplit {
+        TrafficSplit::new(services(weights), canary).unwrap()
+    }
+
+    fn selector(name: &str, value: Option<&str>) -> IngressRouteCanarySelector {
+        IngressRouteCanarySelector {
+            name: name.into(),
+            value: value.map(str::to_string),
+        }
+    }
+
+    fn canary() -> IngressRouteCanary {
+        IngressRouteCanary {
+            service: "beta".into(),
+            headers: vec![
+                selector("x-canary", Some("always")),
+                selector("x-beta-tester", None),
+            ],
+            cookies: vec![selector("canary", Some("1"))],
+            response_header: Some("x-variant".into()),
+        }
+    }
+
+    fn request(headers: &[(&str, &str)]) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        for (name, value) in headers {
+            req.append_header(name.to_string(), *value).unwrap();
+        }
+        req
+    }
+
+    fn selected(split: &TrafficSplit, req: &RequestHeader) -> Option<String> {
+        split.select(req).map(|service| service.name.clone())
+    }
+
+    #[test]
+    fn selects_services_by_weight() {
+        let req = request(&[]);
+        let weighted = split(&[("stable", 3), ("beta", 1), ("off", 0)], None);
+        let stable = (0..4000)
+            .filter_map(|_| selected(&weighted, &req))
+            .inspect(|name| assert_ne!(name, "off"))
+            .filter(|name| name == "stable")
+            .count();
+        assert!((2700..3300).contains(&stable), "{}", stable);
+
+        assert_eq!(selected(&split(&[("a", 0), ("b", 0)], None), &req), None);
+        let single = split(&[("only", 0)], None);
+        assert_eq!(selected(&single, &req).as_deref(), Some("only"));
+    }
+
+    #[test]
+    fn canary_selectors_pin_requests_to_the_canary() {
+        let canary = split(&[("stable", 1), ("beta", 0)], Some(&canary()));
+        for headers in [
+            vec![("x-canary", "always")],
+            vec![("x-beta-tester", "anything")],
+            vec![("cookie", "session=abc; canary=1")],
+        ] {
+            let req = request(&headers);
+            assert_eq!(
+                selected(&canary, &req).as_deref(),
+                Some("beta"),
+                "{:?}",
+                headers
+            );
+        }
+        for headers in [
+            vec![],
+            vec![("x-canary", "sometimes")],
+            vec![("cookie", "canary=0")],
+        ] {
+            let req = request(&headers);
+            assert_eq!(
+                selected(&canary, &req).as_deref(),
+                Some("stable"),
+                "{:?}",
+                headers
+            );
+        }
+    }
+
+    #[test]
+    fn variant_header_names_the_selected_service() {
+        let with_canary = split(&[("stable", 1), ("beta", 0)], Some(&canary()));
+        assert_eq!(with_canary.variant_header(), Some("x-variant"));
+        assert_eq!(with_canary.canary(), Some("beta"));
+        let weighted = split(&[("stable", 1), ("beta", 1)], None);
+        assert_eq!(weighted.variant_header(), Some("x-ferrix-variant"));
+        assert_eq!(split(&[("stable", 1)], None).variant_header(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_canaries() {
+        let stable = || services(&[("stable", 1)]);
+        assert!(TrafficSplit::new(stable(), Some(&canary())).is_err());
+        let no_selectors = IngressRouteCanary {
+            service: "stable".into(),
+            headers: Vec::new(),
+            cookies: Vec::new(),
+            response_header: None,
+        };
+        assert!(TrafficSplit::new(stable(), Some(&no_selectors)).is_err());
+    }
+}
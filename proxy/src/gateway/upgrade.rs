@@ -0,0 +1,59 @@
+use crds::IngressRouteUpgrade;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_IDLE_TIMEOUT_SECONDS: u64 = 3600;
+
+#[derive(Clone)]
+pub struct Upgrade {
+    pub allowed: bool,
+    pub idle_timeout: Duration,
+    active: Arc<AtomicUsize>,
+}
+
+pub struct UpgradeGuard(Arc<AtomicUsize>);
+
+impl Drop for UpgradeGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for Upgrade {
+    fn default() -> Self {
+        Self {
+            allowed: true,
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECONDS),
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl From<&IngressRouteUpgrade> for Upgrade {
+    fn from(spec: &IngressRouteUpgrade) -> Self {
+        let default = Self::default();
+        Self {
+            allowed: spec.allowed.unwrap_or(default.allowed),
+            idle_timeout: spec
+                .idle_timeout_seconds
+                .map_or(default.idle_timeout, Duration::from_secs),
+            ..default
+        }
+    }
+}
+
+impl Upgrade {
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn track(&self) -> UpgradeGuard {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        UpgradeGuard(self.active.clone())
+    }
+
+    pub fn inherit(&mut self, previous: &Upgrade) {
+        self.active = previous.active.clone();
+    }
+}
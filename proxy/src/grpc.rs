@@ -0,0 +1,112 @@
+use pingora::http::{RequestHeader, ResponseHeader, StatusCode};
+use pingora::prelude::Session;
+use std::time::Duration;
+
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+pub fn is_grpc(req: &RequestHeader) -> bool {
+    req.headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(GRPC_CONTENT_TYPE))
+}
+
+pub fn timeout(req: &RequestHeader) -> Option<Duration> {
+    let value = req.headers.get(GRPC_TIMEOUT_HEADER)?.to_str().ok()?;
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount = amount.parse::<u64>().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount.saturating_mul(3600))),
+        "M" => Some(Duration::from_secs(amount.saturating_mul(60))),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+pub fn status(code: u16, timed_out: bool) -> (u32, &'static str) {
+    if timed_out {
+        return (4, "deadline exceeded");
+    }
+    match code {
+        400 => (13, "bad request"),
+        401 => (16, "unauthenticated"),
+        403 => (7, "permission denied"),
+        404 => (12, "no route for request"),
+        429 => (14, "too many requests"),
+        502..=504 => (14, "upstream unavailable"),
+        _ => (2, "unknown error"),
+    }
+}
+
+pub async fn respond_error(session: &mut Session, status: (u32, &str)) -> pingora::Result<()> {
+    let (code, message) = status;
+    let mut response = ResponseHeader::build(StatusCode::OK, Some(3))?;
+    response.insert_header("content-type", GRPC_CONTENT_TYPE)?;
+    response.insert_header("grpc-status", code.to_string())?;
+    response.insert_header("grpc-message", message)?;
+    session
+        .write_response_header(Box::new(response), true)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{status, timeout};
+    use pingora::http::RequestHeader;
+    use std::time::Duration;
+
+    fn parse(value: &str) -> Option<Duration> {
+        let mut req = RequestHeader::build("POST", b"/svc/Method", None).unwrap();
+        req.insert_header("grpc-timeout", value).unwrap();
+        timeout(&req)
+    }
+
+    #[test]
+    fn parses_every_timeout_unit() {
+        assert_eq!(parse("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(parse("4S"), Some(Duration::from_secs(4)));
+        assert_eq!(parse("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(parse("99999999u"), Some(Duration::from_micros(99_999_999)));
+        assert_eq!(parse("1n"), Some(Duration::from_nanos(1)));
+        let req = RequestHeader::build("POST", b"/svc/Method", None).unwrap();
+        assert_eq!(timeout(&req), None);
+    }
+
+    #[test]
+    fn rejects_malformed_timeouts() {
+        for value in ["", "S", "5", "123456789S", "5s", "5x", "-5S", "+5S", "1.5S"] {
+            assert_eq!(parse(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn maps_http_errors_to_grpc_codes() {
+        assert_eq!(status(504, true).0, 4);
+        assert_eq!(status(200, true).0, 4);
+        for (http, grpc) in [
+            (400, 13),
+            (401, 16),
+            (403, 7),
+            (404, 12),
+            (429, 14),
+            (502, 14),
+            (503, 14),
+            (504, 14),
+            (500, 2),
+            (418, 2),
+        ] {
+            assert_eq!(status(http, false).0, grpc, "{}", http);
+        }
+    }
+}
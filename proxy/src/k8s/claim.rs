@@ -0,0 +1,63 @@
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::Resource;
+use std::sync::Arc;
+
+pub trait Claim {
+    fn created(&self) -> DateTime<Utc>;
+    fn name(&self) -> &str;
+}
+
+impl<T: Claim> Claim for Arc<T> {
+    fn created(&self) -> DateTime<Utc> {
+        self.as_ref().created()
+    }
+
+    fn name(&self) -> &str {
+        self.as_ref().name()
+    }
+}
+
+pub fn created<T: Resource>(object: &T) -> DateTime<Utc> {
+    object
+        .meta()
+        .creation_timestamp
+        .as_ref()
+        .map_or(DateTime::<Utc>::MAX_UTC, |t| t.0)
+}
+
+// The oldest claim wins, then the lowest name, so a newer duplicate never takes over from a
+// working route. The others are returned so they can be reported until the winner goes away.
+pub fn oldest<T: Claim>(mut claims: Vec<T>) -> Option<(T, Vec<T>)> {
+    claims.sort_by(|a, b| (a.created(), a.name()).cmp(&(b.created(), b.name())));
+    let mut claims = claims.into_iter();
+    claims.next().map(|winner| (winner, claims.collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{oldest, Claim};
+    use k8s_openapi::chrono::{DateTime, Utc};
+
+    struct Route(i64, &'static str);
+
+    impl Claim for Route {
+        fn created(&self) -> DateTime<Utc> {
+            DateTime::<Utc>::from_timestamp(self.0, 0).unwrap()
+        }
+
+        fn name(&self) -> &str {
+            self.1
+        }
+    }
+
+    #[test]
+    fn oldest_claim_wins_and_ties_go_to_the_lowest_name() {
+        assert!(oldest(Vec::<Route>::new()).is_none());
+
+        let claims = vec![Route(2, "a"), Route(1, "c"), Route(1, "b"), Route(3, "d")];
+        let (winner, ignored) = oldest(claims).unwrap();
+        assert_eq!(winner.name(), "b");
+        let ignored = ignored.iter().map(Claim::name).collect::<Vec<_>>();
+        assert_eq!(ignored, ["c", "a", "d"]);
+    }
+}
@@ -0,0 +1,19 @@
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::ConfigMap;
+
+pub fn get_key(config_map: &ConfigMap, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let name = config_map.metadata.name.clone().unwrap_or_default();
+    config_map
+        .data
+        .as_ref()
+        .and_then(|data| data.get(key))
+        .map(|value| value.as_bytes().to_vec())
+        .or_else(|| {
+            config_map
+                .binary_data
+                .as_ref()
+                .and_then(|data| data.get(key))
+                .map(|value| value.0.clone())
+        })
+        .ok_or(anyhow!("config map {} has no {} key", name, key))
+}
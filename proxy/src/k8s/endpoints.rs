@@ -1,6 +1,27 @@
+use crate::k8s;
 use k8s_openapi::api::core::v1::Endpoints;
+use kube::runtime::watcher;
+use kube::runtime::watcher::Event;
+use log::error;
+use std::sync::Arc;
+use tokio::sync::Notify;
 
-pub fn get_ip_addresses(endpoints: Endpoints, port: u16) -> Vec<String> {
+pub struct Address {
+    pub ip: String,
+    pub port: u16,
+    pub pod: Option<String>,
+}
+
+impl Address {
+    pub fn socket_address(&self) -> String {
+        match self.ip.contains(':') {
+            true => format!("[{}]:{}", self.ip, self.port),
+            false => format!("{}:{}", self.ip, self.port),
+        }
+    }
+}
+
+pub fn get_addresses(endpoints: Endpoints, port: u16) -> Vec<Address> {
     endpoints
         .subsets
         .unwrap_or_default()
@@ -10,7 +31,62 @@ pub fn get_ip_addresses(endpoints: Endpoints, port: u16) -> Vec<String> {
                 .addresses
                 .unwrap_or_default()
                 .into_iter()
-                .map(|addr| format!("{}:{}", addr.ip, port))
+                .map(move |addr| Address {
+                    ip: addr.ip,
+                    port,
+                    pod: addr.target_ref.and_then(|r| r.name),
+                })
         })
         .collect()
 }
+
+pub fn watch<F>(
+    client: kube::Client,
+    namespace: String,
+    name: String,
+    port: u16,
+    notify: Arc<Notify>,
+    update: F,
+) where
+    F: Fn(Vec<Address>) + Send + 'static,
+{
+    tokio::spawn(async move {
+        let watch_opts = watcher::Config {
+            field_selector: Some(format!(
+                "metadata.name={},metadata.namespace={}",
+                name, namespace
+            )),
+            ..Default::default()
+        };
+        let mut watch = match k8s::watcher::create::<Endpoints>(client, watch_opts).await {
+            Ok(w) => w,
+            Err(e) => {
+                error!("Unable to create endpoints watcher: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = notify.notified() => {
+                    break;
+                }
+                event = watch.recv() => match event {
+                    Some(event) => {
+                        let endpoints = match event {
+                            Event::Applied(e) => vec![e],
+                            Event::Deleted(_) => continue,
+                            Event::Restarted(e) => e
+                        };
+
+                        let Some(ep) = endpoints.last() else {
+                            continue;
+                        };
+                        update(get_addresses(ep.clone(), port));
+                    }
+                    None => break
+                }
+            }
+        }
+    });
+}
@@ -2,7 +2,12 @@ use kube::Resource;
 use serde::de::DeserializeOwned;
 use std::fmt::Debug;
 
+pub mod claim;
+pub mod config_maps;
 pub mod endpoints;
+pub mod secrets;
+#[cfg(test)]
+pub mod testing;
 pub mod watcher;
 
 pub trait Object: Resource + Clone + DeserializeOwned + Debug + Send + 'static {}
@@ -0,0 +1,34 @@
+use anyhow::anyhow;
+use k8s_openapi::api::core::v1::Secret;
+
+const TLS_SECRET_TYPE: &str = "kubernetes.io/tls";
+
+pub fn get_tls_pair(secret: &Secret) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+    let name = secret.metadata.name.clone().unwrap_or_default();
+    if secret.type_.as_deref() != Some(TLS_SECRET_TYPE) {
+        return Err(anyhow!(
+            "secret {} is not of type {}",
+            name,
+            TLS_SECRET_TYPE
+        ));
+    }
+
+    let data = secret.data.clone().unwrap_or_default();
+    let cert = data
+        .get("tls.crt")
+        .ok_or(anyhow!("secret {} has no tls.crt key", name))?;
+    let key = data
+        .get("tls.key")
+        .ok_or(anyhow!("secret {} has no tls.key key", name))?;
+    Ok((cert.0.clone(), key.0.clone()))
+}
+
+pub fn get_key(secret: &Secret, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let name = secret.metadata.name.clone().unwrap_or_default();
+    secret
+        .data
+        .as_ref()
+        .and_then(|data| data.get(key))
+        .map(|value| value.0.clone())
+        .ok_or(anyhow!("secret {} has no {} key", name, key))
+}
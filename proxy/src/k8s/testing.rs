@@ -0,0 +1,25 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+// Points at a closed port so API calls fail fast instead of reaching a cluster.
+pub fn client() -> kube::Client {
+    let config = kube::Config::new("http://127.0.0.1:9".parse().unwrap());
+    kube::Client::try_from(config).unwrap()
+}
+
+// Builds a ferrix custom resource in the default namespace, using the name as its uid.
+pub fn resource<T: DeserializeOwned>(kind: &str, name: &str, created: &str, spec: Value) -> T {
+    serde_json::from_value(json!({
+        "apiVersion": "ferrix.com/v1",
+        "kind": kind,
+        "metadata": {
+            "name": name,
+            "namespace": "default",
+            "uid": name,
+            "generation": 1,
+            "creationTimestamp": created,
+        },
+        "spec": spec,
+    }))
+    .unwrap()
+}
@@ -1,49 +1,73 @@
 use crate::k8s::Object;
 use anyhow::anyhow;
 use async_trait::async_trait;
-use crds::IngressRoute;
 use futures_util::future::BoxFuture;
-use futures_util::TryStreamExt;
+use futures_util::{Stream, StreamExt};
 use kube::runtime::watcher::Event;
 use kube::runtime::{watcher, WatchStreamExt};
 use kube::{Api, Resource};
 use log::{debug, error, info};
 use pingora::server::ShutdownWatch;
 use pingora::services::background::BackgroundService;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::marker::PhantomData;
 use std::pin::pin;
 use tokio::select;
 use tokio::sync::mpsc;
 
-pub struct Service<F>
+pub struct Service<T, F, D>
 where
-    F: Fn(kube::client::Client, IngressRoute) -> BoxFuture<'static, Result<(), anyhow::Error>>
+    T: Object + Sync,
+    <T as Resource>::DynamicType: Default,
+    F: Fn(kube::client::Client, T) -> BoxFuture<'static, Result<(), anyhow::Error>>
+        + Send
+        + Sync
+        + 'static,
+    D: Fn(kube::client::Client, T) -> BoxFuture<'static, Result<(), anyhow::Error>>
         + Send
         + Sync
         + 'static,
 {
     update: F,
+    delete: D,
     failure: mpsc::Sender<anyhow::Error>,
+    _object: PhantomData<T>,
 }
 
-impl<F> Service<F>
+impl<T, F, D> Service<T, F, D>
 where
-    F: Fn(kube::client::Client, IngressRoute) -> BoxFuture<'static, Result<(), anyhow::Error>>
+    T: Object + Sync,
+    <T as Resource>::DynamicType: Default,
+    F: Fn(kube::client::Client, T) -> BoxFuture<'static, Result<(), anyhow::Error>>
+        + Send
+        + Sync
+        + 'static,
+    D: Fn(kube::client::Client, T) -> BoxFuture<'static, Result<(), anyhow::Error>>
         + Send
         + Sync
         + 'static,
 {
-    pub fn new(update: F, failure_bus: mpsc::Sender<anyhow::Error>) -> Self {
+    pub fn new(update: F, delete: D, failure_bus: mpsc::Sender<anyhow::Error>) -> Self {
         Self {
             update,
+            delete,
             failure: failure_bus,
+            _object: PhantomData,
         }
     }
 }
 
 #[async_trait]
-impl<F> BackgroundService for Service<F>
+impl<T, F, D> BackgroundService for Service<T, F, D>
 where
-    F: Fn(kube::client::Client, IngressRoute) -> BoxFuture<'static, Result<(), anyhow::Error>>
+    T: Object + Sync,
+    <T as Resource>::DynamicType: Default,
+    F: Fn(kube::client::Client, T) -> BoxFuture<'static, Result<(), anyhow::Error>>
+        + Send
+        + Sync
+        + 'static,
+    D: Fn(kube::client::Client, T) -> BoxFuture<'static, Result<(), anyhow::Error>>
         + Send
         + Sync
         + 'static,
@@ -68,22 +92,22 @@ where
 
         debug!("Kubernetes client acquisition successful");
         debug!("Creating Kubernetes watcher is running");
-        let mut watch =
-            match create::<IngressRoute>(client.clone(), watcher::Config::default()).await {
-                Ok(w) => w,
-                Err(e) => {
-                    if let Err(e) = self
-                        .failure
-                        .clone()
-                        .send(anyhow!("unable to create Kubernetes watcher: {}", e))
-                        .await
-                    {
-                        error!("Error sending error result failure channel: {}", e);
-                    }
-                    return;
+        let mut watch = match create::<T>(client.clone(), watcher::Config::default()).await {
+            Ok(w) => w,
+            Err(e) => {
+                if let Err(e) = self
+                    .failure
+                    .clone()
+                    .send(anyhow!("unable to create Kubernetes watcher: {}", e))
+                    .await
+                {
+                    error!("Error sending error result failure channel: {}", e);
                 }
-            };
+                return;
+            }
+        };
 
+        let mut known = HashMap::new();
         loop {
             select! {
                 _ = shutdown.changed() => {
@@ -94,31 +118,62 @@ where
                     Some(event) => {
                         debug!("Received a watch event");
 
-                        let routes= match event {
-                            Event::Deleted(route) => {
-                                vec![route]
-                            }
-                            Event::Applied(route) => {
-                                vec![route]
+                        let (applied, deleted) = changes(&mut known, event);
+                        for route in deleted {
+                            if let Err(e) = (self.delete)(client.clone(), route).await {
+                                error!("Error running watch service delete: {}", e);
                             }
-                            Event::Restarted(routes) => {
-                                routes
-                            }
-                        };
-
-                        for route in routes {
+                        }
+                        for route in applied {
                             if let Err(e) = (self.update)(client.clone(), route).await {
                                 error!("Error running watch service update: {}", e);
                             }
                         }
                     },
-                    None => continue,
+                    None => {
+                        if let Err(e) = self
+                            .failure
+                            .clone()
+                            .send(anyhow!("Kubernetes watch stream closed"))
+                            .await
+                        {
+                            error!("Error sending error result failure channel: {}", e);
+                        }
+                        break;
+                    }
                 }
             }
         }
     }
 }
 
+// Splits an event into applied and deleted objects. A restart relists everything, so objects
+// deleted while the watch was down are only noticed by their absence.
+fn changes<T: Resource + Clone>(
+    known: &mut HashMap<String, T>,
+    event: Event<T>,
+) -> (Vec<T>, Vec<T>) {
+    let uid = |object: &T| object.meta().uid.clone().unwrap_or_default();
+    match event {
+        Event::Applied(object) => {
+            known.insert(uid(&object), object.clone());
+            (vec![object], Vec::new())
+        }
+        Event::Deleted(object) => {
+            known.remove(&uid(&object));
+            (Vec::new(), vec![object])
+        }
+        Event::Restarted(objects) => {
+            let mut previous = std::mem::take(known);
+            for object in &objects {
+                previous.remove(&uid(object));
+                known.insert(uid(object), object.clone());
+            }
+            (objects, previous.into_values().collect())
+        }
+    }
+}
+
 pub async fn create<T: Object>(
     client: kube::client::Client,
     config: watcher::Config,
@@ -128,23 +183,107 @@ where
 {
     let api = Api::all(client);
     let (tx, rx) = mpsc::channel(16);
-    tokio::spawn(async move {
-        let stream = watcher(api, config).default_backoff();
-        let mut stream = pin!(stream);
-        loop {
-            select! {
-                event = stream.try_next() => match event {
-                    Ok(e) => {
-                        if let Some(event) = e {
-                            tx.send(event).await.unwrap();
-                        }
-                    },
-                    Err(e) => {
-                        error!("Unable to read from stream: {}", e);
+    tokio::spawn(forward(watcher(api, config).default_backoff(), tx));
+    Ok(rx)
+}
+
+async fn forward<T, E: Display>(stream: impl Stream<Item = Result<T, E>>, tx: mpsc::Sender<T>) {
+    let mut stream = pin!(stream);
+    loop {
+        select! {
+            _ = tx.closed() => break,
+            event = stream.next() => match event {
+                Some(Ok(event)) => {
+                    if tx.send(event).await.is_err() {
+                        break;
                     }
                 }
+                Some(Err(e)) => error!("Unable to read from stream: {}", e),
+                None => break,
             }
         }
-    });
-    Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changes, forward};
+    use futures_util::stream;
+    use k8s_openapi::api::core::v1::ConfigMap;
+    use kube::api::ObjectMeta;
+    use kube::runtime::watcher::Event;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn forward_stops_when_receiver_dropped_before_event() {
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+        let events = stream::iter(vec![Ok::<_, String>(1), Ok(2)]);
+        timeout(Duration::from_secs(1), forward(events, tx))
+            .await
+            .expect("forward should stop once the receiver is gone");
+    }
+
+    #[tokio::test]
+    async fn forward_stops_when_receiver_dropped_while_idle() {
+        let (tx, rx) = mpsc::channel::<u32>(1);
+        let task = tokio::spawn(forward(stream::pending::<Result<u32, String>>(), tx));
+        drop(rx);
+        timeout(Duration::from_secs(1), task)
+            .await
+            .expect("forward should stop once the receiver is gone")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn forward_delivers_events_until_receiver_dropped() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let events = stream::iter((0..100).map(Ok::<_, String>));
+        let task = tokio::spawn(forward(events, tx));
+        assert_eq!(rx.recv().await, Some(0));
+        drop(rx);
+        timeout(Duration::from_secs(1), task)
+            .await
+            .expect("forward should stop once the receiver is gone")
+            .unwrap();
+    }
+
+    fn object(uid: &str) -> ConfigMap {
+        ConfigMap {
+            metadata: ObjectMeta {
+                uid: Some(uid.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn uids(objects: Vec<ConfigMap>) -> Vec<String> {
+        let mut uids = objects
+            .into_iter()
+            .filter_map(|o| o.metadata.uid)
+            .collect::<Vec<_>>();
+        uids.sort();
+        uids
+    }
+
+    #[test]
+    fn restart_deletes_objects_missing_from_the_relist() {
+        let mut known = HashMap::new();
+        changes(&mut known, Event::Applied(object("a")));
+        changes(&mut known, Event::Applied(object("b")));
+
+        let (applied, deleted) = changes(&mut known, Event::Deleted(object("a")));
+        assert!(applied.is_empty());
+        assert_eq!(uids(deleted), ["a"]);
+
+        changes(&mut known, Event::Applied(object("c")));
+        let (applied, deleted) =
+            changes(&mut known, Event::Restarted(vec![object("c"), object("d")]));
+        assert_eq!(uids(applied), ["c", "d"]);
+        assert_eq!(uids(deleted), ["b"]);
+    }
 }
@@ -0,0 +1,129 @@
+use crate::session;
+use crds::IngressRouteStickySession;
+use hmac::{Hmac, Mac};
+use pingora::lb::Backend;
+use pingora::prelude::Session;
+use rand::RngCore;
+use sha2::Sha256;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+const DEFAULT_COOKIE_NAME: &str = "ferrix_affinity";
+
+static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Sets the key used to sign affinity cookies. Replicas sharing a key honour each
+/// other's cookies; without one every process generates its own at first use.
+pub fn set_secret(secret: Vec<u8>) -> Result<(), Vec<u8>> {
+    SECRET.set(secret)
+}
+
+fn secret() -> &'static [u8] {
+    SECRET.get_or_init(|| {
+        let mut key = vec![0; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        key
+    })
+}
+
+#[derive(Clone)]
+pub struct Affinity {
+    cookie_name: String,
+    attributes: String,
+    scope: String,
+}
+
+impl Affinity {
+    pub fn new(spec: &IngressRouteStickySession, scope: &str) -> Self {
+        let mut attributes = format!("; Path={}", spec.path.as_deref().unwrap_or("/"));
+        if let Some(max_age) = spec.max_age_seconds {
+            attributes.push_str(&format!("; Max-Age={}", max_age));
+        }
+        if let Some(same_site) = &spec.same_site {
+            attributes.push_str(&format!("; SameSite={}", same_site));
+        }
+        if spec.secure.unwrap_or(false) {
+            attributes.push_str("; Secure");
+        }
+        if spec.http_only.unwrap_or(true) {
+            attributes.push_str("; HttpOnly");
+        }
+
+        Self {
+            cookie_name: spec
+                .cookie_name
+                .clone()
+                .unwrap_or(DEFAULT_COOKIE_NAME.to_string()),
+            attributes,
+            scope: scope.to_string(),
+        }
+    }
+
+    pub fn find(&self, session: &Session, backends: &[Backend]) -> Option<Backend> {
+        let value = session::get_cookie(session.req_header(), &self.cookie_name)?;
+        backends
+            .iter()
+            .find(|b| self.cookie_value(b) == value)
+            .cloned()
+    }
+
+    pub fn set_cookie(&self, backend: &Backend) -> String {
+        format!(
+            "{}={}{}",
+            self.cookie_name,
+            self.cookie_value(backend),
+            self.attributes
+        )
+    }
+
+    fn cookie_value(&self, backend: &Backend) -> String {
+        let digest = Hmac::<Sha256>::new_from_slice(secret())
+            .expect("HMAC accepts keys of any length")
+            .chain_update(self.scope.as_bytes())
+            .chain_update(b"|")
+            .chain_update(backend.addr.to_string().as_bytes())
+            .finalize()
+            .into_bytes();
+        digest[..16].iter().fold(String::new(), |mut s, b| {
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Affinity;
+    use crds::IngressRouteStickySession;
+    use pingora::lb::Backend;
+
+    fn affinity(scope: &str) -> Affinity {
+        Affinity::new(&IngressRouteStickySession::default(), scope)
+    }
+
+    #[test]
+    fn cookie_value_is_stable_and_scoped() {
+        let backend = Backend::new("10.0.0.1:8080").unwrap();
+        let other = Backend::new("10.0.0.2:8080").unwrap();
+        let a = affinity("svc.ns");
+        assert_eq!(a.cookie_value(&backend), a.cookie_value(&backend));
+        assert_ne!(a.cookie_value(&backend), a.cookie_value(&other));
+        assert_ne!(
+            a.cookie_value(&backend),
+            affinity("other.ns").cookie_value(&backend)
+        );
+        assert_eq!(a.cookie_value(&backend).len(), 32);
+    }
+
+    #[test]
+    fn cookie_value_is_not_derivable_from_public_inputs() {
+        use sha2::{Digest, Sha256};
+
+        let backend = Backend::new("10.0.0.1:8080").unwrap();
+        let unkeyed = Sha256::new()
+            .chain_update(b"svc.ns|10.0.0.1:8080")
+            .finalize();
+        let unkeyed: String = unkeyed[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        assert_ne!(affinity("svc.ns").cookie_value(&backend), unkeyed);
+    }
+}
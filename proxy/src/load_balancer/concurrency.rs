@@ -0,0 +1,140 @@
+use crate::load_balancer::Error;
+use crds::IngressRouteConcurrency;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct ConcurrencyLimiter {
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    max_queued: usize,
+    queue_timeout: Duration,
+}
+
+struct QueueGuard(Arc<AtomicUsize>);
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl TryFrom<&IngressRouteConcurrency> for ConcurrencyLimiter {
+    type Error = Error;
+
+    fn try_from(spec: &IngressRouteConcurrency) -> Result<Self, Self::Error> {
+        if spec.max_in_flight == 0 {
+            return Err(Error::Concurrency(
+                "maxInFlight must be greater than 0".into(),
+            ));
+        }
+        Ok(Self {
+            permits: Arc::new(Semaphore::new(spec.max_in_flight)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            max_queued: spec.max_queued.unwrap_or(0),
+            queue_timeout: spec
+                .queue_timeout_millis
+                .map_or(DEFAULT_QUEUE_TIMEOUT, Duration::from_millis),
+        })
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+
+        let queued = self.queued.fetch_add(1, Ordering::Relaxed);
+        let _guard = QueueGuard(self.queued.clone());
+        if queued >= self.max_queued {
+            return None;
+        }
+        tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned())
+            .await
+            .ok()
+            .and_then(Result::ok)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcurrencyLimiter;
+    use crds::IngressRouteConcurrency;
+    use std::time::{Duration, Instant};
+
+    fn limiter(max_queued: usize, queue_timeout_millis: u64) -> ConcurrencyLimiter {
+        ConcurrencyLimiter::try_from(&IngressRouteConcurrency {
+            max_in_flight: 1,
+            max_queued: Some(max_queued),
+            queue_timeout_millis: Some(queue_timeout_millis),
+        })
+        .unwrap()
+    }
+
+    async fn wait_until_queued(limiter: &ConcurrencyLimiter, queued: usize) {
+        while limiter.queued() != queued {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn queued_request_gets_a_released_permit() {
+        let limiter = limiter(1, 5_000);
+        let held = limiter.acquire().await.unwrap();
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.is_some() }
+        });
+        wait_until_queued(&limiter, 1).await;
+
+        drop(held);
+        assert!(waiter.await.unwrap());
+        assert_eq!(limiter.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn queued_request_times_out() {
+        let limiter = limiter(1, 50);
+        let _held = limiter.acquire().await.unwrap();
+        let start = Instant::now();
+        assert!(limiter.acquire().await.is_none());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(limiter.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_when_the_queue_is_full() {
+        let limiter = limiter(1, 5_000);
+        let _held = limiter.acquire().await.unwrap();
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.is_some() }
+        });
+        wait_until_queued(&limiter, 1).await;
+
+        let start = Instant::now();
+        assert!(limiter.acquire().await.is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(limiter.queued(), 1);
+        waiter.abort();
+    }
+
+    #[test]
+    fn rejects_zero_max_in_flight() {
+        let spec = IngressRouteConcurrency {
+            max_in_flight: 0,
+            max_queued: None,
+            queue_timeout_millis: None,
+        };
+        assert!(ConcurrencyLimiter::try_from(&spec).is_err());
+    }
+}
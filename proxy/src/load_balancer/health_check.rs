@@ -0,0 +1,82 @@
+use crate::load_balancer::strategy::Selector;
+use crate::tls::UpstreamTls;
+use crds::{HealthCheckProtocol, IngressRouteHealthCheck};
+use pingora::http::RequestHeader;
+use pingora::lb::health_check::{HealthCheck, HttpHealthCheck, TcpHealthCheck};
+use std::sync::Weak;
+use std::time::Duration;
+
+const DEFAULT_INTERVAL_SECONDS: u64 = 10;
+const DEFAULT_TIMEOUT_SECONDS: u64 = 1;
+const DEFAULT_HEALTHY_THRESHOLD: usize = 1;
+const DEFAULT_UNHEALTHY_THRESHOLD: usize = 3;
+
+pub fn build(
+    spec: &IngressRouteHealthCheck,
+    sni: &str,
+    tls: Option<&UpstreamTls>,
+) -> pingora::Result<Box<dyn HealthCheck + Send + Sync>> {
+    let timeout = Duration::from_secs(spec.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS));
+    let healthy_threshold = spec.healthy_threshold.unwrap_or(DEFAULT_HEALTHY_THRESHOLD);
+    let unhealthy_threshold = spec
+        .unhealthy_threshold
+        .unwrap_or(DEFAULT_UNHEALTHY_THRESHOLD);
+
+    match spec.protocol {
+        HealthCheckProtocol::Tcp => {
+            let mut check = TcpHealthCheck::new();
+            check.consecutive_success = healthy_threshold;
+            check.consecutive_failure = unhealthy_threshold;
+            check.peer_template.options.connection_timeout = Some(timeout);
+            Ok(check)
+        }
+        HealthCheckProtocol::Http => {
+            let sni = tls.and_then(|tls| tls.server_name()).unwrap_or(sni);
+            let host = spec.host.as_deref().unwrap_or(sni);
+            let path = spec.path.as_deref().unwrap_or("/");
+            let expected_status = spec.expected_status.unwrap_or(200);
+
+            let mut check = HttpHealthCheck::new(host, tls.is_some());
+            if let Some(tls) = tls {
+                check.peer_template.sni = sni.to_string();
+                tls.apply(&mut check.peer_template);
+            }
+            check.req = RequestHeader::build("GET", path.as_bytes(), None)?;
+            check.req.insert_header("Host", host)?;
+            check.consecutive_success = healthy_threshold;
+            check.consecutive_failure = unhealthy_threshold;
+            check.peer_template.options.connection_timeout = Some(timeout);
+            check.peer_template.options.read_timeout = Some(timeout);
+            check.validator = Some(Box::new(move |resp| {
+                if resp.status.as_u16() != expected_status {
+                    return pingora::Error::e_explain(
+                        pingora::ErrorType::CustomCode("unexpected status", resp.status.as_u16()),
+                        format!("expected status {}", expected_status),
+                    );
+                }
+                Ok(())
+            }));
+            Ok(Box::new(check))
+        }
+    }
+}
+
+pub fn interval(spec: &IngressRouteHealthCheck) -> Duration {
+    Duration::from_secs(
+        spec.interval_seconds
+            .unwrap_or(DEFAULT_INTERVAL_SECONDS)
+            .max(1),
+    )
+}
+
+pub async fn run(selector: Weak<dyn Selector>, interval: Duration) {
+    loop {
+        let Some(selector) = selector.upgrade() else {
+            break;
+        };
+        selector.backends().run_health_check(true).await;
+        drop(selector);
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -1,58 +1,402 @@
+mod affinity;
+mod concurrency;
+mod discovery;
+mod health_check;
+mod outlier;
+mod strategy;
+mod stream;
+mod timeouts;
+
+pub use affinity::set_secret as set_affinity_secret;
+pub use stream::StreamLoadBalancer;
+pub use timeouts::Timeouts;
+
+use crate::k8s::endpoints::Address;
+use crate::load_balancer::affinity::Affinity;
+use crate::load_balancer::concurrency::ConcurrencyLimiter;
+use crate::load_balancer::discovery::EndpointDiscovery;
+use crate::load_balancer::outlier::OutlierDetector;
+use crate::load_balancer::strategy::{Selector, Strategy};
+use crate::tls::UpstreamTls;
 use async_trait::async_trait;
-use pingora::lb::LoadBalancer;
-use pingora::prelude::{HttpPeer, RoundRobin, Session};
+use axum::http::header::SET_COOKIE;
+use crds::{IngressRouteService, UpstreamProtocol};
+use dashmap::DashMap;
+use pingora::http::{ResponseHeader, StatusCode};
+use pingora::lb::{Backend, Backends};
+use pingora::prelude::{HttpPeer, Session};
+use pingora::protocols::l4::socket::SocketAddr;
+use pingora::protocols::ALPN;
 use pingora::proxy::ProxyHttp;
+use rand::Rng;
+use std::collections::BTreeSet;
 use std::net::ToSocketAddrs;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::OwnedSemaphorePermit;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid backend address: {0}")]
+    Address(std::io::Error),
+
+    #[error("invalid health check: {0}")]
+    HealthCheck(Box<pingora::Error>),
+
+    #[error("invalid load balancing strategy: {0}")]
+    Strategy(String),
+
+    #[error("invalid concurrency limit: {0}")]
+    Concurrency(String),
+
+    #[error("invalid upstream protocol: {0}")]
+    Protocol(String),
+}
+
+const MAX_H2_STREAMS: usize = 100;
+
+#[derive(Default)]
+pub struct Context {
+    backend: Option<Backend>,
+    tried: Vec<SocketAddr>,
+    permit: Option<OwnedSemaphorePermit>,
+    in_flight: Option<InFlightGuard>,
+    affinity_cookie: Option<String>,
+}
+
+pub struct BackendStatus {
+    pub address: String,
+    pub healthy: bool,
+    pub ejected_for: Option<Duration>,
+}
+
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 #[derive(Clone)]
-pub struct RoundRobinLoadBalancer {
+pub struct ServiceLoadBalancer {
     sni: String,
-    load_balancer: Arc<LoadBalancer<RoundRobin>>,
+    strategy: Strategy,
+    affinity: Option<Affinity>,
+    concurrency: Option<ConcurrencyLimiter>,
+    outlier: Option<OutlierDetector>,
+    timeouts: Timeouts,
+    tls: Option<UpstreamTls>,
+    protocol: UpstreamProtocol,
+    discovery: EndpointDiscovery,
+    selector: Arc<dyn Selector>,
+    in_flight: Arc<DashMap<SocketAddr, Arc<AtomicUsize>>>,
+    next: Arc<AtomicUsize>,
 }
 
-impl RoundRobinLoadBalancer {
-    pub fn try_from_iter<A, T: IntoIterator<Item = A>>(
+impl ServiceLoadBalancer {
+    pub fn new(
         sni: &str,
-        addresses: T,
-    ) -> std::io::Result<Self>
-    where
-        A: ToSocketAddrs,
-    {
-        let upstreams = LoadBalancer::try_from_iter(addresses)?;
-        Ok(Self {
+        service: &IngressRouteService,
+        addresses: Vec<Address>,
+        tls: Option<UpstreamTls>,
+    ) -> Result<Self, Error> {
+        match (service.protocol, tls.is_some()) {
+            (UpstreamProtocol::H2, false) => {
+                return Err(Error::Protocol(
+                    "h2 requires scheme https, use h2c for cleartext".into(),
+                ))
+            }
+            (UpstreamProtocol::H2c, true) => {
+                return Err(Error::Protocol(
+                    "h2c requires scheme http, use h2 with https".into(),
+                ))
+            }
+            _ => {}
+        }
+        let strategy = Strategy::try_from(&service.strategy)?;
+        let discovery = EndpointDiscovery::default();
+        let mut backends = Backends::new(Box::new(discovery.clone()));
+        if let Some(spec) = &service.health_check {
+            backends.set_health_check(
+                health_check::build(spec, sni, tls.as_ref()).map_err(Error::HealthCheck)?,
+            );
+        }
+
+        let lb = Self {
             sni: sni.to_string(),
-            load_balancer: Arc::new(upstreams),
-        })
+            selector: strategy.build_selector(backends),
+            strategy,
+            affinity: service.sticky.as_ref().map(|spec| Affinity::new(spec, sni)),
+            concurrency: service
+                .concurrency
+                .as_ref()
+                .map(ConcurrencyLimiter::try_from)
+                .transpose()?,
+            outlier: service
+                .outlier_detection
+                .as_ref()
+                .map(OutlierDetector::from),
+            timeouts: service
+                .timeouts
+                .as_ref()
+                .map(Timeouts::from)
+                .unwrap_or_default(),
+            tls,
+            protocol: service.protocol,
+            discovery,
+            in_flight: Arc::new(DashMap::new()),
+            next: Arc::new(AtomicUsize::new(0)),
+        };
+        lb.update(addresses)?;
+
+        if let Some(spec) = &service.health_check {
+            tokio::spawn(health_check::run(
+                Arc::downgrade(&lb.selector),
+                health_check::interval(spec),
+            ));
+        }
+        Ok(lb)
+    }
+
+    pub fn update(&self, addresses: Vec<Address>) -> Result<(), Error> {
+        let backends = resolve(addresses, |address| self.strategy.weight(address))?;
+        self.in_flight
+            .retain(|addr, _| backends.iter().any(|b| b.addr == *addr));
+        if let Some(outlier) = &self.outlier {
+            outlier.retain(|addr| backends.iter().any(|b| b.addr == *addr));
+        }
+        self.discovery.set(backends);
+        self.selector.update();
+        Ok(())
     }
 
     pub fn get_sni(&self) -> String {
         self.sni.clone()
     }
 
-    pub fn get_ip_addresses(self) -> Vec<String> {
-        let backends = self.load_balancer.backends();
+    pub fn get_backends(&self) -> Vec<BackendStatus> {
+        let backends = self.selector.backends();
+        backends
+            .get_backend()
+            .iter()
+            .map(|b| BackendStatus {
+                address: b.addr.to_string(),
+                healthy: backends.ready(b),
+                ejected_for: self.outlier.as_ref().and_then(|o| o.ejected_for(&b.addr)),
+            })
+            .collect()
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn queued(&self) -> usize {
+        self.concurrency.as_ref().map_or(0, |c| c.queued())
+    }
+
+    fn select(&self, session: &Session, tried: &[SocketAddr]) -> Option<Backend> {
+        let accept = |b: &Backend| self.accept(b, tried);
+        match &self.strategy {
+            Strategy::LeastRequests => self.select_least_requests(tried),
+            Strategy::PowerOfTwoChoices => self.select_power_of_two_choices(tried),
+            Strategy::ConsistentHash(key) => self.selector.select(&key.extract(session), &accept),
+            _ => self.selector.select(b"", &accept),
+        }
+    }
+
+    fn select_least_requests(&self, tried: &[SocketAddr]) -> Option<Backend> {
+        let ready = self.ready_backends(tried);
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..ready.len())
+            .map(|i| &ready[(start + i) % ready.len()])
+            .min_by_key(|b| self.in_flight_count(b))
+            .cloned()
+    }
+
+    fn select_power_of_two_choices(&self, tried: &[SocketAddr]) -> Option<Backend> {
+        let mut ready = self.ready_backends(tried);
+        if ready.len() < 2 {
+            return ready.pop();
+        }
+
+        let mut rng = rand::thread_rng();
+        let first = rng.gen_range(0..ready.len());
+        let mut second = rng.gen_range(0..ready.len() - 1);
+        if second >= first {
+            second += 1;
+        }
+
+        if self.in_flight_count(&ready[second]) < self.in_flight_count(&ready[first]) {
+            return Some(ready.swap_remove(second));
+        }
+        Some(ready.swap_remove(first))
+    }
+
+    fn ready_backends(&self, tried: &[SocketAddr]) -> Vec<Backend> {
+        let backends = self.selector.backends();
         backends
             .get_backend()
             .iter()
-            .map(|b| b.addr.to_string())
+            .filter(|b| backends.ready(b) && self.accept(b, tried))
+            .cloned()
             .collect()
     }
+
+    fn accept(&self, backend: &Backend, tried: &[SocketAddr]) -> bool {
+        !self.is_ejected(backend) && !tried.contains(&backend.addr)
+    }
+
+    fn is_ejected(&self, backend: &Backend) -> bool {
+        self.outlier
+            .as_ref()
+            .is_some_and(|o| o.is_ejected(&backend.addr))
+    }
+
+    fn record_outcome(&self, ctx: &Context, success: bool) {
+        if let (Some(outlier), Some(backend)) = (&self.outlier, &ctx.backend) {
+            let backends = self.selector.backends().get_backend().len();
+            outlier.record(&backend.addr, success, backends);
+        }
+    }
+
+    fn in_flight_count(&self, backend: &Backend) -> usize {
+        self.in_flight
+            .get(&backend.addr)
+            .map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+
+    fn track(&self, backend: &Backend) -> InFlightGuard {
+        let counter = self
+            .in_flight
+            .entry(backend.addr.clone())
+            .or_default()
+            .clone();
+        counter.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(counter)
+    }
+}
+
+fn resolve(
+    addresses: Vec<Address>,
+    weight: impl Fn(&Address) -> usize,
+) -> Result<BTreeSet<Backend>, Error> {
+    let mut backends = BTreeSet::new();
+    for address in addresses {
+        let weight = weight(&address);
+        for addr in address
+            .socket_address()
+            .to_socket_addrs()
+            .map_err(Error::Address)?
+        {
+            backends.insert(Backend {
+                addr: SocketAddr::Inet(addr),
+                weight,
+                ext: Default::default(),
+            });
+        }
+    }
+    Ok(backends)
 }
 
 #[async_trait]
-impl ProxyHttp for RoundRobinLoadBalancer {
-    type CTX = ();
+impl ProxyHttp for ServiceLoadBalancer {
+    type CTX = Context;
 
-    fn new_ctx(&self) -> Self::CTX {}
+    fn new_ctx(&self) -> Self::CTX {
+        Context::default()
+    }
 
     async fn upstream_peer(
         &self,
-        _session: &mut Session,
-        _ctx: &mut Self::CTX,
+        session: &mut Session,
+        ctx: &mut Self::CTX,
     ) -> pingora::Result<Box<HttpPeer>> {
-        let upstream = self.load_balancer.select(b"", 256).unwrap();
-        let peer = Box::new(HttpPeer::new(upstream, false, self.sni.clone()));
+        if let (Some(limiter), None) = (&self.concurrency, &ctx.permit) {
+            let permit = limiter.acquire().await.ok_or_else(|| {
+                pingora::Error::explain(
+                    pingora::ErrorType::HTTPStatus(StatusCode::SERVICE_UNAVAILABLE.as_u16()),
+                    "Service concurrency limit reached",
+                )
+            })?;
+            ctx.permit = Some(permit);
+        }
+
+        let pinned = self
+            .affinity
+            .as_ref()
+            .and_then(|affinity| affinity.find(session, &self.ready_backends(&ctx.tried)));
+        let upstream = match pinned {
+            Some(backend) => backend,
+            None => {
+                let backend = self
+                    .select(session, &ctx.tried)
+                    .or_else(|| self.select(session, &[]))
+                    .ok_or_else(|| {
+                        pingora::Error::explain(
+                            pingora::ErrorType::HTTPStatus(
+                                StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                            ),
+                            "No healthy upstream endpoints available",
+                        )
+                    })?;
+                ctx.affinity_cookie = self.affinity.as_ref().map(|a| a.set_cookie(&backend));
+                backend
+            }
+        };
+        ctx.in_flight = Some(self.track(&upstream));
+        ctx.tried.push(upstream.addr.clone());
+        ctx.backend = Some(upstream.clone());
+        let sni = self
+            .tls
+            .as_ref()
+            .and_then(|tls| tls.server_name())
+            .unwrap_or(&self.sni);
+        let mut peer = Box::new(HttpPeer::new(upstream, self.tls.is_some(), sni.to_string()));
+        if let Some(tls) = &self.tls {
+            tls.apply(&mut peer);
+        }
+        peer.options.alpn = match self.protocol {
+            UpstreamProtocol::Http1 => ALPN::H1,
+            UpstreamProtocol::H2 | UpstreamProtocol::H2c => ALPN::H2,
+        };
+        if self.protocol != UpstreamProtocol::Http1 {
+            peer.options.max_h2_streams = MAX_H2_STREAMS;
+        }
         Ok(peer)
     }
+
+    fn fail_to_connect(
+        &self,
+        _session: &mut Session,
+        _peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        e: Box<pingora::Error>,
+    ) -> Box<pingora::Error> {
+        self.record_outcome(ctx, false);
+        e
+    }
+
+    async fn response_filter(
+        &self,
+        _session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        self.record_outcome(ctx, !upstream_response.status.is_server_error());
+        if let Some(cookie) = ctx.affinity_cookie.take() {
+            upstream_response.append_header(SET_COOKIE, cookie)?;
+        }
+        Ok(())
+    }
 }
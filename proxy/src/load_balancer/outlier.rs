@@ -0,0 +1,243 @@
+use crds::IngressRouteOutlierDetection;
+use dashmap::DashMap;
+use log::warn;
+use pingora::protocols::l4::socket::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CONSECUTIVE_FAILURES: u32 = 5;
+const DEFAULT_MINIMUM_REQUESTS: u32 = 10;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_BASE_EJECTION: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_EJECTION: Duration = Duration::from_secs(300);
+const DEFAULT_MAX_EJECTED_PERCENT: u32 = 10;
+
+struct Stats {
+    consecutive_failures: u32,
+    requests: u32,
+    failures: u32,
+    window_start: Instant,
+    ejections: u32,
+    ejected_until: Option<Instant>,
+}
+
+impl Stats {
+    fn new(now: Instant) -> Self {
+        Self {
+            consecutive_failures: 0,
+            requests: 0,
+            failures: 0,
+            window_start: now,
+            ejections: 0,
+            ejected_until: None,
+        }
+    }
+
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until.is_some_and(|until| until > now)
+    }
+}
+
+#[derive(Clone)]
+pub struct OutlierDetector {
+    consecutive_failures: u32,
+    failure_percentage: Option<u32>,
+    minimum_requests: u32,
+    interval: Duration,
+    base_ejection: Duration,
+    max_ejection: Duration,
+    max_ejected_percent: u32,
+    stats: Arc<DashMap<SocketAddr, Stats>>,
+}
+
+impl From<&IngressRouteOutlierDetection> for OutlierDetector {
+    fn from(spec: &IngressRouteOutlierDetection) -> Self {
+        Self {
+            consecutive_failures: spec
+                .consecutive_failures
+                .unwrap_or(DEFAULT_CONSECUTIVE_FAILURES),
+            failure_percentage: spec.failure_percentage,
+            minimum_requests: spec.minimum_requests.unwrap_or(DEFAULT_MINIMUM_REQUESTS),
+            interval: spec
+                .interval_seconds
+                .map_or(DEFAULT_INTERVAL, Duration::from_secs),
+            base_ejection: spec
+                .base_ejection_seconds
+                .map_or(DEFAULT_BASE_EJECTION, Duration::from_secs),
+            max_ejection: spec
+                .max_ejection_seconds
+                .map_or(DEFAULT_MAX_EJECTION, Duration::from_secs),
+            max_ejected_percent: spec
+                .max_ejected_percent
+                .unwrap_or(DEFAULT_MAX_EJECTED_PERCENT),
+            stats: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+impl OutlierDetector {
+    pub fn is_ejected(&self, addr: &SocketAddr) -> bool {
+        self.stats
+            .get(addr)
+            .is_some_and(|s| s.is_ejected(Instant::now()))
+    }
+
+    pub fn ejected_for(&self, addr: &SocketAddr) -> Option<Duration> {
+        let now = Instant::now();
+        self.stats
+            .get(addr)
+            .and_then(|s| s.ejected_until)
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn retain(&self, f: impl Fn(&SocketAddr) -> bool) {
+        self.stats.retain(|addr, _| f(addr));
+    }
+
+    pub fn record(&self, addr: &SocketAddr, success: bool, backends: usize) {
+        let now = Instant::now();
+        let ejected = self
+            .stats
+            .iter()
+            .filter(|s| s.key() != addr && s.is_ejected(now))
+            .count();
+
+        let mut stats = self
+            .stats
+            .entry(addr.clone())
+            .or_insert_with(|| Stats::new(now));
+        if now.duration_since(stats.window_start) >= self.interval {
+            if stats.failures == 0 {
+                stats.ejections = stats.ejections.saturating_sub(1);
+            }
+            stats.requests = 0;
+            stats.failures = 0;
+            stats.window_start = now;
+        }
+
+        stats.requests += 1;
+        if success {
+            stats.consecutive_failures = 0;
+            return;
+        }
+        stats.failures += 1;
+        stats.consecutive_failures += 1;
+        if stats.is_ejected(now) {
+            return;
+        }
+
+        let failure_rate = self.failure_percentage.is_some_and(|percentage| {
+            stats.requests >= self.minimum_requests
+                && stats.failures * 100 >= percentage * stats.requests
+        });
+        if stats.consecutive_failures < self.consecutive_failures && !failure_rate {
+            return;
+        }
+        if ejected * 100 >= self.max_ejected_percent as usize * backends {
+            return;
+        }
+
+        let ejection = self
+            .base_ejection
+            .saturating_mul(2u32.saturating_pow(stats.ejections))
+            .min(self.max_ejection);
+        warn!("Ejecting backend {} for {:?}", addr, ejection);
+        stats.ejections += 1;
+        stats.ejected_until = Some(now + ejection);
+        stats.consecutive_failures = 0;
+        stats.requests = 0;
+        stats.failures = 0;
+        stats.window_start = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutlierDetector;
+    use crds::IngressRouteOutlierDetection;
+    use pingora::protocols::l4::socket::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    fn detector(spec: IngressRouteOutlierDetection) -> OutlierDetector {
+        OutlierDetector::from(&IngressRouteOutlierDetection {
+            base_ejection_seconds: Some(10),
+            max_ejection_seconds: Some(25),
+            max_ejected_percent: spec.max_ejected_percent.or(Some(100)),
+            ..spec
+        })
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::Inet(([127, 0, 0, 1], port).into())
+    }
+
+    fn record(detector: &OutlierDetector, addr: &SocketAddr, results: &[bool]) {
+        for success in results {
+            detector.record(addr, *success, 4);
+        }
+    }
+
+    fn ejected_for(detector: &OutlierDetector, addr: &SocketAddr) -> u64 {
+        // Round up, as some time has passed since the ejection started.
+        detector.ejected_for(addr).map_or(0, |d| d.as_secs() + 1)
+    }
+
+    #[test]
+    fn ejects_after_consecutive_failures() {
+        let detector = detector(IngressRouteOutlierDetection {
+            consecutive_failures: Some(3),
+            ..Default::default()
+        });
+        let backend = addr(1);
+        record(&detector, &backend, &[false, false, true, false, false]);
+        assert!(!detector.is_ejected(&backend));
+        record(&detector, &backend, &[false]);
+        assert!(detector.is_ejected(&backend));
+        assert_eq!(ejected_for(&detector, &backend), 10);
+    }
+
+    #[test]
+    fn ejects_above_the_failure_percentage() {
+        let detector = detector(IngressRouteOutlierDetection {
+            consecutive_failures: Some(100),
+            failure_percentage: Some(50),
+            minimum_requests: Some(4),
+            ..Default::default()
+        });
+        let backend = addr(1);
+        // Two of three requests failed, but too few requests were seen to judge.
+        record(&detector, &backend, &[false, true, false]);
+        assert!(!detector.is_ejected(&backend));
+        record(&detector, &backend, &[true, false]);
+        assert!(detector.is_ejected(&backend));
+    }
+
+    #[test]
+    fn ejections_back_off_exponentially_up_to_the_maximum() {
+        let detector = detector(IngressRouteOutlierDetection {
+            consecutive_failures: Some(1),
+            ..Default::default()
+        });
+        let backend = addr(1);
+        for expected in [10, 20, 25] {
+            record(&detector, &backend, &[false]);
+            assert_eq!(ejected_for(&detector, &backend), expected);
+            detector.stats.get_mut(&backend).unwrap().ejected_until =
+                Some(Instant::now() - Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn caps_the_share_of_ejected_backends() {
+        let detector = detector(IngressRouteOutlierDetection {
+            consecutive_failures: Some(1),
+            max_ejected_percent: Some(25),
+            ..Default::default()
+        });
+        record(&detector, &addr(1), &[false]);
+        record(&detector, &addr(2), &[false]);
+        assert!(detector.is_ejected(&addr(1)));
+        assert!(!detector.is_ejected(&addr(2)));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::k8s::endpoints::Address;
+use crate::load_balancer::Error;
+use crate::session;
+use crds::{HashSource, IngressRouteStrategy, LoadBalancingAlgorithm};
+use futures_util::FutureExt;
+use pingora::lb::selection::{BackendIter, BackendSelection, Consistent, Random, RoundRobin};
+use pingora::lb::{Backend, Backends, LoadBalancer};
+use pingora::prelude::Session;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+const MAX_ITERATIONS: usize = 256;
+
+#[derive(Clone)]
+pub enum Strategy {
+    RoundRobin,
+    WeightedRoundRobin(BTreeMap<String, usize>),
+    LeastRequests,
+    PowerOfTwoChoices,
+    Random,
+    ConsistentHash(HashKey),
+}
+
+#[derive(Clone)]
+pub enum HashKey {
+    ClientIp,
+    Header(String),
+    Cookie(String),
+    Path,
+}
+
+impl TryFrom<&IngressRouteStrategy> for Strategy {
+    type Error = Error;
+
+    fn try_from(spec: &IngressRouteStrategy) -> Result<Self, Self::Error> {
+        let strategy = match spec.algorithm {
+            LoadBalancingAlgorithm::RoundRobin => Strategy::RoundRobin,
+            LoadBalancingAlgorithm::WeightedRoundRobin => {
+                Strategy::WeightedRoundRobin(spec.weights.clone().unwrap_or_default())
+            }
+            LoadBalancingAlgorithm::LeastRequests => Strategy::LeastRequests,
+            LoadBalancingAlgorithm::PowerOfTwoChoices => Strategy::PowerOfTwoChoices,
+            LoadBalancingAlgorithm::Random => Strategy::Random,
+            LoadBalancingAlgorithm::ConsistentHash => {
+                let key = match spec.hash_on.unwrap_or_default() {
+                    HashSource::ClientIp => HashKey::ClientIp,
+                    HashSource::Path => HashKey::Path,
+                    HashSource::Header => HashKey::Header(Self::hash_key(spec, "header")?),
+                    HashSource::Cookie => HashKey::Cookie(Self::hash_key(spec, "cookie")?),
+                };
+                Strategy::ConsistentHash(key)
+            }
+        };
+        Ok(strategy)
+    }
+}
+
+impl Strategy {
+    fn hash_key(spec: &IngressRouteStrategy, source: &str) -> Result<String, Error> {
+        spec.hash_key
+            .clone()
+            .filter(|k| !k.is_empty())
+            .ok_or_else(|| Error::Strategy(format!("hashing on {} requires a hashKey", source)))
+    }
+
+    pub fn weight(&self, address: &Address) -> usize {
+        let Strategy::WeightedRoundRobin(weights) = self else {
+            return 1;
+        };
+        address
+            .pod
+            .as_ref()
+            .and_then(|pod| weights.get(pod))
+            .or_else(|| weights.get(&address.ip))
+            .copied()
+            .unwrap_or(1)
+    }
+
+    pub fn build_selector(&self, backends: Backends) -> Arc<dyn Selector> {
+        match self {
+            Strategy::Random => Arc::new(LoadBalancer::<Random>::from_backends(backends)),
+            Strategy::ConsistentHash(_) => {
+                Arc::new(LoadBalancer::<Consistent>::from_backends(backends))
+            }
+            _ => Arc::new(LoadBalancer::<RoundRobin>::from_backends(backends)),
+        }
+    }
+}
+
+impl HashKey {
+    pub fn extract(&self, session: &Session) -> Vec<u8> {
+        let req = session.req_header();
+        let key = match self {
+            HashKey::ClientIp => None,
+            HashKey::Header(name) => req
+                .headers
+                .get(name.as_str())
+                .map(|v| v.as_bytes().to_vec()),
+            HashKey::Cookie(name) => session::get_cookie(req, name).map(|v| v.as_bytes().to_vec()),
+            HashKey::Path => Some(req.uri.path().as_bytes().to_vec()),
+        };
+
+        key.or_else(|| session::client_ip(session).map(|ip| ip.to_string().into_bytes()))
+            .unwrap_or_default()
+    }
+}
+
+pub trait Selector: Send + Sync {
+    fn backends(&self) -> &Backends;
+
+    fn update(&self);
+
+    fn select(&self, key: &[u8], accept: &dyn Fn(&Backend) -> bool) -> Option<Backend>;
+}
+
+impl<S> Selector for LoadBalancer<S>
+where
+    S: BackendSelection + Send + Sync + 'static,
+    S::Iter: BackendIter,
+{
+    fn backends(&self) -> &Backends {
+        LoadBalancer::backends(self)
+    }
+
+    fn update(&self) {
+        LoadBalancer::update(self)
+            .now_or_never()
+            .expect("endpoint discovery should not block")
+            .expect("endpoint discovery should not error");
+    }
+
+    fn select(&self, key: &[u8], accept: &dyn Fn(&Backend) -> bool) -> Option<Backend> {
+        LoadBalancer::select_with(self, key, MAX_ITERATIONS, |backend, healthy| {
+            healthy && accept(backend)
+        })
+    }
+}
@@ -0,0 +1,41 @@
+use crate::k8s::endpoints::Address;
+use crate::load_balancer::discovery::EndpointDiscovery;
+use crate::load_balancer::strategy::{Selector, Strategy};
+use crate::load_balancer::{resolve, Error};
+use pingora::lb::{Backend, Backends};
+use pingora::protocols::l4::socket::SocketAddr;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct StreamLoadBalancer {
+    discovery: EndpointDiscovery,
+    selector: Arc<dyn Selector>,
+}
+
+impl StreamLoadBalancer {
+    pub fn new(addresses: Vec<Address>) -> Result<Self, Error> {
+        let discovery = EndpointDiscovery::default();
+        let backends = Backends::new(Box::new(discovery.clone()));
+        let lb = Self {
+            selector: Strategy::RoundRobin.build_selector(backends),
+            discovery,
+        };
+        lb.update(addresses)?;
+        Ok(lb)
+    }
+
+    pub fn update(&self, addresses: Vec<Address>) -> Result<(), Error> {
+        self.discovery.set(resolve(addresses, |_| 1)?);
+        self.selector.update();
+        Ok(())
+    }
+
+    pub fn contains(&self, addr: &std::net::SocketAddr) -> bool {
+        self.discovery.contains(&SocketAddr::Inet(*addr))
+    }
+
+    pub fn select(&self, tried: &[SocketAddr]) -> Option<Backend> {
+        self.selector
+            .select(b"", &|backend| !tried.contains(&backend.addr))
+    }
+}
@@ -0,0 +1,52 @@
+use crds::IngressRouteTimeouts;
+use pingora::prelude::HttpPeer;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Timeouts {
+    pub connect_millis: Option<u64>,
+    pub read_millis: Option<u64>,
+    pub write_millis: Option<u64>,
+    pub idle_millis: Option<u64>,
+    pub total_millis: Option<u64>,
+}
+
+impl From<&IngressRouteTimeouts> for Timeouts {
+    fn from(spec: &IngressRouteTimeouts) -> Self {
+        Self {
+            connect_millis: spec.connect_millis,
+            read_millis: spec.read_millis,
+            write_millis: spec.write_millis,
+            idle_millis: spec.idle_millis,
+            total_millis: spec.total_millis,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn total(&self) -> Option<Duration> {
+        self.total_millis.map(Duration::from_millis)
+    }
+
+    pub fn or(&self, defaults: &Timeouts) -> Timeouts {
+        Timeouts {
+            connect_millis: self.connect_millis.or(defaults.connect_millis),
+            read_millis: self.read_millis.or(defaults.read_millis),
+            write_millis: self.write_millis.or(defaults.write_millis),
+            idle_millis: self.idle_millis.or(defaults.idle_millis),
+            total_millis: self.total_millis.or(defaults.total_millis),
+        }
+    }
+
+    pub fn apply(&self, peer: &mut HttpPeer) {
+        let options = &mut peer.options;
+        let millis = |value: Option<u64>, current: Option<Duration>| {
+            value.map(Duration::from_millis).or(current)
+        };
+        options.connection_timeout = millis(self.connect_millis, options.connection_timeout);
+        options.read_timeout = millis(self.read_millis, options.read_timeout);
+        options.write_timeout = millis(self.write_millis, options.write_timeout);
+        options.idle_timeout = millis(self.idle_millis, options.idle_timeout);
+    }
+}
@@ -1,20 +1,32 @@
-use crate::gateway::{Gateway, SharedGateway};
+use crate::gateway::{Gateway, Redirect, SharedGateway};
+use crate::server::Protocol;
 use anyhow::anyhow;
 use clap::Parser;
 use dashmap::DashMap;
 use log::{error, info};
 use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
+use pingora::apps::HttpServerOptions;
+use pingora::listeners::tls::TlsSettings;
 use pingora::prelude::background_service;
 use pingora::proxy::http_proxy_service;
+use pingora::services::listening::Service;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
 
 mod api;
 mod gateway;
+mod grpc;
 mod k8s;
 mod load_balancer;
+mod matcher;
+mod middleware;
 mod server;
+mod session;
+mod tcp;
+mod tls;
+mod udp;
 
 #[derive(Parser, Debug)]
 #[command(version, about = "I'm a turnip", long_about = None)]
@@ -35,6 +47,12 @@ struct CliArgs {
 
     #[arg(long, help = "Port to run the HTTP API", default_value_t = 8080)]
     api_port: u16,
+
+    #[arg(
+        long,
+        help = "File holding the key that signs sticky session cookies, shared between replicas"
+    )]
+    affinity_secret_file: Option<String>,
 }
 
 fn main() {
@@ -50,19 +68,110 @@ fn main() {
 
 fn run(args: CliArgs) -> Result<(), anyhow::Error> {
     let config = server::config::load(&args.config_file)?;
+    if let Some(path) = &args.affinity_secret_file {
+        let secret = std::fs::read(path)
+            .map_err(|e| anyhow!("Unable to read affinity secret {}: {}", path, e))?;
+        let secret = secret.trim_ascii();
+        if secret.is_empty() {
+            return Err(anyhow!("Affinity secret {} is empty", path));
+        }
+        let _ = load_balancer::set_affinity_secret(secret.to_vec());
+    }
     let mut server = server::new(config.server);
     let (watch_failure_tx, mut watch_failure_rx) = tokio::sync::mpsc::channel(1);
 
     server.bootstrap();
 
-    let entry_points = DashMap::with_capacity(config.entry_points.len());
+    let entry_points = Arc::new(DashMap::with_capacity(config.entry_points.len()));
     let route_tables = DashMap::with_capacity(config.entry_points.len());
+    let conflict_tables = DashMap::with_capacity(config.entry_points.len());
+    let tcp_entry_points = Arc::new(DashMap::new());
+    let udp_entry_points = Arc::new(DashMap::new());
+    let redirects = config
+        .entry_points
+        .iter()
+        .filter_map(|ep| ep.redirect_to.as_ref().map(|target| (ep, target)))
+        .map(|(ep, target)| {
+            config
+                .entry_points
+                .iter()
+                .find(|t| &t.name == target)
+                .map(|t| (ep.name.clone(), Redirect::to_entry_point(t.secure, t.port)))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Entry point {} redirects to unknown entry point {}",
+                        ep.name,
+                        target
+                    )
+                })
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
     for ep in config.entry_points {
-        let gateway = SharedGateway::new(Gateway::new());
+        let address = format!("[::]:{}", ep.port);
+        if ep.protocol == Protocol::Tcp {
+            let router = tcp::SharedRouter::new(tcp::Router::default());
+            let mut service = Service::new(format!("TCP entry point {}", ep.name), router.clone());
+            service.add_tcp(&address);
+            server.add_service(service);
+            tcp_entry_points.insert(ep.name.clone(), router);
+            continue;
+        }
+        if ep.protocol == Protocol::Udp {
+            let router = udp::SharedRouter::new(udp::Router::new(ep.port));
+            server.add_service(background_service(
+                &format!("UDP entry point {}", ep.name),
+                router.clone(),
+            ));
+            udp_entry_points.insert(ep.name.clone(), router);
+            continue;
+        }
+
+        let certificates = ep.secure.then(tls::CertificateStore::default);
+        let redirect = redirects.get(&ep.name).cloned();
+        let gateway = SharedGateway::new(Gateway::new(
+            ep.port,
+            certificates.clone(),
+            redirect,
+            ep.timeouts.clone(),
+        ));
         route_tables.insert(ep.name.clone(), gateway.get_route_table());
+        conflict_tables.insert(ep.name.clone(), gateway.get_conflicts());
         let mut proxy = http_proxy_service(&server.configuration, gateway.clone());
 
-        proxy.add_tcp(format!("[::]:{}", ep.port).as_str());
+        match certificates {
+            Some(certificates) => {
+                let default_certificate = ep
+                    .default_certificate
+                    .map(|c| tls::Certificate::from_files(&c.cert_file, &c.key_file))
+                    .transpose()
+                    .map_err(|e| {
+                        anyhow!(
+                            "Invalid default certificate for entry point {}: {}",
+                            ep.name,
+                            e
+                        )
+                    })?;
+                let resolver = tls::CertificateResolver::new(
+                    certificates,
+                    gateway.get_route_table(),
+                    default_certificate,
+                );
+                let mut settings = TlsSettings::with_callbacks(Box::new(resolver))
+                    .map_err(|e| anyhow!("Unable to create TLS settings: {}", e))?;
+                settings.enable_h2();
+                proxy.add_tls_with_settings(&address, None, settings);
+            }
+            None => {
+                if ep.h2c {
+                    let mut options = HttpServerOptions::default();
+                    options.h2c = true;
+                    if let Some(app) = proxy.app_logic_mut() {
+                        app.server_options = Some(options);
+                    }
+                }
+                proxy.add_tcp(&address)
+            }
+        }
         server.add_service(proxy);
         entry_points.insert(ep.name.clone(), gateway.clone());
     }
@@ -70,16 +179,41 @@ fn run(args: CliArgs) -> Result<(), anyhow::Error> {
     server.add_services(vec![Box::new(background_service(
         "Kubernetes IngressRoute watcher",
         k8s::watcher::Service::new(
-            Gateway::update_route_tables(Arc::new(entry_points)),
-            watch_failure_tx,
+            Gateway::update_route_tables(entry_points.clone()),
+            Gateway::delete_routes(entry_points),
+            watch_failure_tx.clone(),
         ),
     ))]);
+    if !tcp_entry_points.is_empty() {
+        server.add_service(background_service(
+            "Kubernetes IngressRouteTCP watcher",
+            k8s::watcher::Service::new(
+                tcp::Router::update_route_tables(tcp_entry_points.clone()),
+                tcp::Router::delete_routes(tcp_entry_points),
+                watch_failure_tx.clone(),
+            ),
+        ));
+    }
+    if !udp_entry_points.is_empty() {
+        server.add_service(background_service(
+            "Kubernetes IngressRouteUDP watcher",
+            k8s::watcher::Service::new(
+                udp::Router::update_route_tables(udp_entry_points.clone()),
+                udp::Router::delete_routes(udp_entry_points),
+                watch_failure_tx,
+            ),
+        ));
+    }
 
     if args.api_enabled {
         info!("Starting up HTTP API");
         server.add_service(background_service(
             "API",
-            api::Service::new(args.api_port, Arc::new(route_tables)),
+            api::Service::new(
+                args.api_port,
+                Arc::new(route_tables),
+                Arc::new(conflict_tables),
+            ),
         ))
     }
 
@@ -0,0 +1,158 @@
+mod parser;
+
+use crate::session;
+use ipnet::IpNet;
+use pingora::http::{Method, RequestHeader};
+use pingora::prelude::Session;
+use regex::Regex;
+use std::net::IpAddr;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("unexpected character '{0}' at position {1}")]
+    UnexpectedChar(char, usize),
+
+    #[error("unterminated string starting at position {0}")]
+    UnterminatedString(usize),
+
+    #[error("unexpected token '{0}' at position {1}")]
+    UnexpectedToken(String, usize),
+
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+
+    #[error("unknown matcher '{0}'")]
+    UnknownMatcher(String),
+
+    #[error("{0} expects {1} argument(s), got {2}")]
+    Arity(String, String, usize),
+
+    #[error("invalid regular expression '{0}': {1}")]
+    InvalidRegex(String, regex::Error),
+
+    #[error("invalid HTTP method '{0}'")]
+    InvalidMethod(String),
+
+    #[error("invalid IP address or CIDR range '{0}'")]
+    InvalidClientIp(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Any,
+    Path(String),
+    PathPrefix(String),
+    PathRegexp(Regex),
+    Header(String, String),
+    HeaderRegexp(String, Regex),
+    Method(Method),
+    Query(String, Option<String>),
+    ClientIp(IpNet),
+    Not(Box<Matcher>),
+    And(Box<Matcher>, Box<Matcher>),
+    Or(Box<Matcher>, Box<Matcher>),
+}
+
+impl FromStr for Matcher {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parser::parse(s)
+    }
+}
+
+impl Matcher {
+    pub fn matches(&self, session: &Session) -> bool {
+        self.evaluate(session.req_header(), session::client_ip(session))
+    }
+
+    // Exact paths outrank prefixes of the same length, path length dominates the
+    // other predicates, and an Or is only as specific as its loosest branch.
+    pub fn specificity(&self) -> i64 {
+        match self {
+            Matcher::Any | Matcher::Not(_) => 0,
+            Matcher::Path(path) => 2 * path.len() as i64 + 1,
+            Matcher::PathPrefix(prefix) => 2 * prefix.len() as i64,
+            Matcher::PathRegexp(re) => re.as_str().len() as i64,
+            Matcher::Header(..)
+            | Matcher::HeaderRegexp(..)
+            | Matcher::Method(_)
+            | Matcher::Query(..)
+            | Matcher::ClientIp(_) => 1,
+            Matcher::And(lhs, rhs) => lhs.specificity() + rhs.specificity(),
+            Matcher::Or(lhs, rhs) => lhs.specificity().min(rhs.specificity()),
+        }
+    }
+
+    pub fn evaluate(&self, req: &RequestHeader, client_ip: Option<IpAddr>) -> bool {
+        match self {
+            Matcher::Any => true,
+            Matcher::Path(path) => req.uri.path() == path,
+            Matcher::PathPrefix(prefix) => req.uri.path().starts_with(prefix.as_str()),
+            Matcher::PathRegexp(re) => re.is_match(req.uri.path()),
+            Matcher::Header(name, value) => req
+                .headers
+                .get_all(name.as_str())
+                .iter()
+                .any(|v| v.as_bytes() == value.as_bytes()),
+            Matcher::HeaderRegexp(name, re) => req
+                .headers
+                .get_all(name.as_str())
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .any(|v| re.is_match(v)),
+            Matcher::Method(method) => req.method == method,
+            Matcher::Query(key, value) => req
+                .uri
+                .query()
+                .unwrap_or_default()
+                .split('&')
+                .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+                .any(|(k, v)| k == key && value.as_ref().is_none_or(|value| v == value)),
+            Matcher::ClientIp(net) => client_ip.is_some_and(|ip| net.contains(&ip)),
+            Matcher::Not(m) => !m.evaluate(req, client_ip),
+            Matcher::And(lhs, rhs) => lhs.evaluate(req, client_ip) && rhs.evaluate(req, client_ip),
+            Matcher::Or(lhs, rhs) => lhs.evaluate(req, client_ip) || rhs.evaluate(req, client_ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Matcher;
+
+    fn specificity(matches: &str) -> i64 {
+        matches.parse::<Matcher>().unwrap().specificity()
+    }
+
+    #[test]
+    fn specific_path_outranks_several_trivial_matchers() {
+        let path = specificity("Path(`/api/v1/users`)");
+        let trivial = specificity("Method(`GET`) && Header(`x-a`, `b`) && Query(`c`)");
+        assert!(path > trivial, "{} <= {}", path, trivial);
+    }
+
+    #[test]
+    fn exact_path_outranks_prefix_of_same_length() {
+        assert!(specificity("Path(`/api`)") > specificity("PathPrefix(`/api`)"));
+    }
+
+    #[test]
+    fn longer_prefix_outranks_shorter_prefix() {
+        assert!(specificity("PathPrefix(`/api/v1`)") > specificity("PathPrefix(`/api`)"));
+    }
+
+    #[test]
+    fn conjunction_adds_and_disjunction_takes_loosest_branch() {
+        let prefix = specificity("PathPrefix(`/api`)");
+        assert_eq!(
+            specificity("PathPrefix(`/api`) && Method(`GET`)"),
+            prefix + 1
+        );
+        assert_eq!(specificity("PathPrefix(`/api`) || Method(`GET`)"), 1);
+        assert_eq!(specificity("!PathPrefix(`/api`)"), 0);
+        assert_eq!(specificity(""), 0);
+    }
+}
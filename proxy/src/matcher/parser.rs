@@ -0,0 +1,425 @@
+use crate::matcher::{Error, Matcher};
+use ipnet::IpNet;
+use pingora::http::Method;
+use regex::Regex;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(String),
+    LParen,
+    RParen,
+    Comma,
+    And,
+    Or,
+    Not,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(i) => write!(f, "{}", i),
+            Token::Literal(l) => write!(f, "`{}`", l),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '!' => Token::Not,
+            '&' | '|' => match chars.next() {
+                Some((_, next)) if next == c && c == '&' => Token::And,
+                Some((_, next)) if next == c => Token::Or,
+                _ => return Err(Error::UnexpectedChar(c, pos)),
+            },
+            '`' | '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, ch)) => literal.push(ch),
+                        None => return Err(Error::UnterminatedString(pos)),
+                    }
+                }
+                Token::Literal(literal)
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut ident = String::from(c);
+                while let Some((_, ch)) = chars.peek() {
+                    if !ch.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    ident.push(*ch);
+                    chars.next();
+                }
+                Token::Ident(ident)
+            }
+            c => return Err(Error::UnexpectedChar(c, pos)),
+        };
+        tokens.push((token, pos));
+    }
+    Ok(tokens)
+}
+
+pub fn parse(input: &str) -> Result<Matcher, Error> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(Matcher::Any);
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let matcher = parser.parse_or()?;
+    if let Some((token, pos)) = parser.tokens.get(parser.pos) {
+        return Err(Error::UnexpectedToken(token.to_string(), *pos));
+    }
+    Ok(matcher)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn next(&mut self) -> Result<(Token, usize), Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(Error::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        let (token, pos) = self.next()?;
+        if token != expected {
+            return Err(Error::UnexpectedToken(token.to_string(), pos));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Matcher, Error> {
+        let mut lhs = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Matcher::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Matcher, Error> {
+        let mut lhs = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let rhs = self.parse_unary()?;
+            lhs = Matcher::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Matcher, Error> {
+        match self.next()? {
+            (Token::Not, _) => Ok(Matcher::Not(Box::new(self.parse_unary()?))),
+            (Token::LParen, _) => {
+                let matcher = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(matcher)
+            }
+            (Token::Ident(name), _) => {
+                let args = self.parse_args()?;
+                predicate(&name, args)
+            }
+            (token, pos) => Err(Error::UnexpectedToken(token.to_string(), pos)),
+        }
+    }
+
+    fn parse_args(&mut self) -> Result<Vec<String>, Error> {
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        loop {
+            match self.next()? {
+                (Token::Literal(arg), _) => args.push(arg),
+                (Token::RParen, _) if args.is_empty() => return Ok(args),
+                (token, pos) => return Err(Error::UnexpectedToken(token.to_string(), pos)),
+            }
+            match self.next()? {
+                (Token::Comma, _) => continue,
+                (Token::RParen, _) => return Ok(args),
+                (token, pos) => return Err(Error::UnexpectedToken(token.to_string(), pos)),
+            }
+        }
+    }
+}
+
+fn predicate(name: &str, args: Vec<String>) -> Result<Matcher, Error> {
+    match name {
+        "Path" => any_of(name, args, |a| Ok(Matcher::Path(a))),
+        "PathPrefix" => any_of(name, args, |a| Ok(Matcher::PathPrefix(a))),
+        "PathRegexp" => {
+            let [pattern] = exactly(name, args)?;
+            Ok(Matcher::PathRegexp(regex(pattern)?))
+        }
+        "Header" => {
+            let [header, value] = exactly(name, args)?;
+            Ok(Matcher::Header(header.to_lowercase(), value))
+        }
+        "HeaderRegexp" => {
+            let [header, pattern] = exactly(name, args)?;
+            Ok(Matcher::HeaderRegexp(
+                header.to_lowercase(),
+                regex(pattern)?,
+            ))
+        }
+        "Method" => any_of(name, args, |a| {
+            Method::from_str(&a.to_uppercase())
+                .map(Matcher::Method)
+                .map_err(|_| Error::InvalidMethod(a))
+        }),
+        "Query" => {
+            let len = args.len();
+            let mut args = args.into_iter();
+            match (args.next(), args.next(), args.next()) {
+                (Some(key), value, None) => Ok(Matcher::Query(key, value)),
+                _ => Err(Error::Arity(name.to_string(), "1 or 2".into(), len)),
+            }
+        }
+        "ClientIP" => any_of(name, args, |a| {
+            let net = match a.parse::<IpNet>() {
+                Ok(net) => net,
+                Err(_) => a
+                    .parse::<IpAddr>()
+                    .map(IpNet::from)
+                    .map_err(|_| Error::InvalidClientIp(a))?,
+            };
+            Ok(Matcher::ClientIp(net))
+        }),
+        _ => Err(Error::UnknownMatcher(name.to_string())),
+    }
+}
+
+fn any_of<F>(name: &str, args: Vec<String>, f: F) -> Result<Matcher, Error>
+where
+    F: Fn(String) -> Result<Matcher, Error>,
+{
+    if args.is_empty() {
+        return Err(Error::Arity(name.to_string(), "at least 1".into(), 0));
+    }
+    args.into_iter()
+        .map(f)
+        .reduce(|lhs, rhs| Ok(Matcher::Or(Box::new(lhs?), Box::new(rhs?))))
+        .unwrap()
+}
+
+fn exactly<const N: usize>(name: &str, args: Vec<String>) -> Result<[String; N], Error> {
+    let len = args.len();
+    args.try_into()
+        .map_err(|_| Error::Arity(name.to_string(), N.to_string(), len))
+}
+
+fn regex(pattern: String) -> Result<Regex, Error> {
+    Regex::new(&pattern).map_err(|e| Error::InvalidRegex(pattern, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::matcher::Matcher;
+
+    fn tree(matcher: &Matcher) -> String {
+        match matcher {
+            Matcher::Any => "Any".into(),
+            Matcher::Path(p) => format!("Path({})", p),
+            Matcher::PathPrefix(p) => format!("PathPrefix({})", p),
+            Matcher::PathRegexp(re) => format!("PathRegexp({})", re),
+            Matcher::Header(h, v) => format!("Header({}, {})", h, v),
+            Matcher::HeaderRegexp(h, re) => format!("HeaderRegexp({}, {})", h, re),
+            Matcher::Method(m) => format!("Method({})", m),
+            Matcher::Query(k, v) => format!("Query({}, {:?})", k, v),
+            Matcher::ClientIp(net) => format!("ClientIP({})", net),
+            Matcher::Not(m) => format!("!{}", tree(m)),
+            Matcher::And(lhs, rhs) => format!("({} && {})", tree(lhs), tree(rhs)),
+            Matcher::Or(lhs, rhs) => format!("({} || {})", tree(lhs), tree(rhs)),
+        }
+    }
+
+    fn parsed(input: &str) -> String {
+        tree(&parse(input).unwrap())
+    }
+
+    fn error(input: &str) -> String {
+        parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn empty_expression_matches_anything() {
+        assert_eq!(parsed(""), "Any");
+        assert_eq!(parsed("  \t"), "Any");
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parsed("Path(`/a`) || Path(`/b`) && Method(`GET`)"),
+            "(Path(/a) || (Path(/b) && Method(GET)))"
+        );
+        assert_eq!(
+            parsed("Path(`/a`) && Path(`/b`) || Method(`GET`)"),
+            "((Path(/a) && Path(/b)) || Method(GET))"
+        );
+    }
+
+    #[test]
+    fn operators_are_left_associative() {
+        assert_eq!(
+            parsed("Path(`/a`) || Path(`/b`) || Path(`/c`)"),
+            "((Path(/a) || Path(/b)) || Path(/c))"
+        );
+    }
+
+    #[test]
+    fn parentheses_and_negation_override_precedence() {
+        assert_eq!(
+            parsed("(Path(`/a`) || Path(`/b`)) && !Method(`POST`)"),
+            "((Path(/a) || Path(/b)) && !Method(POST))"
+        );
+        assert_eq!(parsed("!!Path(`/a`)"), "!!Path(/a)");
+        assert_eq!(
+            parsed("!(Path(`/a`) && Method(`GET`))"),
+            "!(Path(/a) && Method(GET))"
+        );
+    }
+
+    #[test]
+    fn multiple_arguments_expand_to_or() {
+        assert_eq!(
+            parsed("PathPrefix(`/a`, `/b`, `/c`)"),
+            "((PathPrefix(/a) || PathPrefix(/b)) || PathPrefix(/c))"
+        );
+        assert_eq!(
+            parsed("Method(`get`, `post`)"),
+            "(Method(GET) || Method(POST))"
+        );
+    }
+
+    #[test]
+    fn literals_accept_backticks_and_double_quotes() {
+        assert_eq!(parsed(r#"Path("/a")"#), "Path(/a)");
+        assert_eq!(parsed("Path(`/a`)"), "Path(/a)");
+        assert_eq!(
+            parsed(r#"Header(`X-Quote`, `say "hi"`)"#),
+            r#"Header(x-quote, say "hi")"#
+        );
+        assert_eq!(parsed(r#"Header("x-tick", "a`b")"#), "Header(x-tick, a`b)");
+        assert_eq!(parsed("Path(`/a b&&c||d`)"), "Path(/a b&&c||d)");
+        assert_eq!(parsed("Path(``)"), "Path()");
+    }
+
+    #[test]
+    fn predicates_parse_their_arguments() {
+        assert_eq!(parsed("Query(`debug`)"), "Query(debug, None)");
+        assert_eq!(parsed("Query(`a`, `1`)"), "Query(a, Some(\"1\"))");
+        assert_eq!(parsed("ClientIP(`10.0.0.1`)"), "ClientIP(10.0.0.1/32)");
+        assert_eq!(parsed("ClientIP(`10.0.0.0/8`)"), "ClientIP(10.0.0.0/8)");
+        assert_eq!(parsed("PathRegexp(`^/v[0-9]+`)"), "PathRegexp(^/v[0-9]+)");
+        assert_eq!(
+            parsed("HeaderRegexp(`Accept`, `json$`)"),
+            "HeaderRegexp(accept, json$)"
+        );
+    }
+
+    #[test]
+    fn tokenizer_errors_report_position() {
+        assert_eq!(
+            error("Path(`/a`) & Method(`GET`)"),
+            "unexpected character '&' at position 11"
+        );
+        assert_eq!(
+            error("Path(`/a`) |"),
+            "unexpected character '|' at position 11"
+        );
+        assert_eq!(
+            error("Path(`/a`) ; x"),
+            "unexpected character ';' at position 11"
+        );
+        assert_eq!(
+            error("Path(`/a)"),
+            "unterminated string starting at position 5"
+        );
+        assert_eq!(
+            error(r#"Path("/a`)"#),
+            "unterminated string starting at position 5"
+        );
+    }
+
+    #[test]
+    fn parser_errors_report_token_and_position() {
+        assert_eq!(error("Path(`/a`"), "unexpected end of expression");
+        assert_eq!(error("Path(`/a`) &&"), "unexpected end of expression");
+        assert_eq!(error("(Path(`/a`)"), "unexpected end of expression");
+        assert_eq!(error("Path"), "unexpected end of expression");
+        assert_eq!(error("Path(`/a`))"), "unexpected token ')' at position 10");
+        assert_eq!(
+            error("Path(`/a`) Path(`/b`)"),
+            "unexpected token 'Path' at position 11"
+        );
+        assert_eq!(
+            error("Path(`/a` `/b`)"),
+            "unexpected token '`/b`' at position 10"
+        );
+        assert_eq!(error("Path(`/a`,)"), "unexpected token ')' at position 10");
+        assert_eq!(
+            error("&& Path(`/a`)"),
+            "unexpected token '&&' at position 0"
+        );
+        assert_eq!(error("Path `/a`"), "unexpected token '`/a`' at position 5");
+    }
+
+    #[test]
+    fn predicate_errors_name_the_problem() {
+        assert_eq!(error("Host(`a.com`)"), "unknown matcher 'Host'");
+        assert_eq!(
+            error("Path()"),
+            "Path expects at least 1 argument(s), got 0"
+        );
+        assert_eq!(
+            error("Header(`x-a`)"),
+            "Header expects 2 argument(s), got 1"
+        );
+        assert_eq!(
+            error("PathRegexp(`a`, `b`)"),
+            "PathRegexp expects 1 argument(s), got 2"
+        );
+        assert_eq!(
+            error("Query(`a`, `b`, `c`)"),
+            "Query expects 1 or 2 argument(s), got 3"
+        );
+        assert_eq!(error("Query()"), "Query expects 1 or 2 argument(s), got 0");
+        assert_eq!(error("Method(`GET`, `GE T`)"), "invalid HTTP method 'GE T'");
+        assert_eq!(
+            error("ClientIP(`10.0.0.300`)"),
+            "invalid IP address or CIDR range '10.0.0.300'"
+        );
+        assert!(error("PathRegexp(`(`)").starts_with("invalid regular expression '(': "));
+    }
+}
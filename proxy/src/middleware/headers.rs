@@ -0,0 +1,112 @@
+use crate::middleware::template::Template;
+use crate::middleware::{Error, Variables};
+use axum::http::{HeaderName, HeaderValue};
+use crds::IngressRouteHeaderRules;
+use pingora::http::{RequestHeader, ResponseHeader};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+pub trait Headers {
+    fn values(&self, name: &str) -> Vec<HeaderValue>;
+    fn insert(&mut self, name: String, value: impl TryInto<HeaderValue>) -> pingora::Result<()>;
+    fn append(&mut self, name: String, value: impl TryInto<HeaderValue>) -> pingora::Result<()>;
+    fn remove(&mut self, name: &str);
+}
+
+macro_rules! impl_headers {
+    ($t:ty) => {
+        impl Headers for $t {
+            fn values(&self, name: &str) -> Vec<HeaderValue> {
+                self.headers.get_all(name).iter().cloned().collect()
+            }
+
+            fn insert(
+                &mut self,
+                name: String,
+                value: impl TryInto<HeaderValue>,
+            ) -> pingora::Result<()> {
+                self.insert_header(name, value)
+            }
+
+            fn append(
+                &mut self,
+                name: String,
+                value: impl TryInto<HeaderValue>,
+            ) -> pingora::Result<()> {
+                self.append_header(name, value).map(|_| ())
+            }
+
+            fn remove(&mut self, name: &str) {
+                self.remove_header(name);
+            }
+        }
+    };
+}
+
+impl_headers!(RequestHeader);
+impl_headers!(ResponseHeader);
+
+#[derive(Debug, Clone, Default)]
+pub struct HeaderRules {
+    add: Vec<(String, Template)>,
+    set: Vec<(String, Template)>,
+    remove: Vec<String>,
+    rename: Vec<(String, String)>,
+}
+
+impl TryFrom<&IngressRouteHeaderRules> for HeaderRules {
+    type Error = Error;
+
+    fn try_from(spec: &IngressRouteHeaderRules) -> Result<Self, Self::Error> {
+        let templates = |headers: &BTreeMap<String, String>| {
+            headers
+                .iter()
+                .map(|(name, value)| Ok((header_name(name)?, Template::parse(value)?)))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        Ok(Self {
+            add: templates(&spec.add)?,
+            set: templates(&spec.set)?,
+            remove: spec
+                .remove
+                .iter()
+                .map(|name| header_name(name))
+                .collect::<Result<_, _>>()?,
+            rename: spec
+                .rename
+                .iter()
+                .map(|(from, to)| Ok((header_name(from)?, header_name(to)?)))
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+}
+
+impl HeaderRules {
+    pub fn apply<H: Headers>(&self, headers: &mut H, variables: &Variables) -> pingora::Result<()> {
+        for (from, to) in &self.rename {
+            let values = headers.values(from);
+            if values.is_empty() {
+                continue;
+            }
+            headers.remove(from);
+            for value in values {
+                headers.append(to.clone(), value)?;
+            }
+        }
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, template) in &self.set {
+            headers.insert(name.clone(), template.render(variables))?;
+        }
+        for (name, template) in &self.add {
+            headers.append(name.clone(), template.render(variables))?;
+        }
+        Ok(())
+    }
+}
+
+fn header_name(name: &str) -> Result<String, Error> {
+    HeaderName::from_str(name).map_err(|_| Error::InvalidHeaderName(name.to_string()))?;
+    Ok(name.to_string())
+}
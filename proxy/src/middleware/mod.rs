@@ -0,0 +1,224 @@
+mod headers;
+mod path;
+mod rate_limit;
+mod template;
+
+use crate::middleware::headers::HeaderRules;
+use crate::middleware::path::PathRewrite;
+use crate::middleware::rate_limit::RateLimiter;
+use crate::middleware::template::Template;
+use crds::IngressRouteMiddleware;
+use pingora::http::{RequestHeader, ResponseHeader, StatusCode};
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+use thiserror::Error;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const ORIGINAL_URI_HEADER: &str = "x-original-uri";
+const FORWARDED_PREFIX_HEADER: &str = "x-forwarded-prefix";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid header name '{0}'")]
+    InvalidHeaderName(String),
+
+    #[error("unterminated variable in template '{0}'")]
+    UnterminatedVariable(String),
+
+    #[error("unknown template variable '{0}'")]
+    UnknownVariable(String),
+
+    #[error("invalid regular expression '{0}': {1}")]
+    InvalidRegex(String, regex::Error),
+
+    #[error("invalid rate limit: {0}")]
+    InvalidRateLimit(String),
+}
+
+pub struct Variables {
+    pub client_ip: Option<IpAddr>,
+    pub route: String,
+    pub request_id: String,
+    pub host: BTreeMap<String, String>,
+    pub rate_limit: Option<rate_limit::Status>,
+}
+
+impl Variables {
+    pub fn new(
+        req: &RequestHeader,
+        client_ip: Option<IpAddr>,
+        route: &str,
+        host: BTreeMap<String, String>,
+    ) -> Self {
+        let request_id = req
+            .headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                rand::thread_rng()
+                    .gen::<[u8; 16]>()
+                    .iter()
+                    .fold(String::new(), |mut s, b| {
+                        let _ = write!(s, "{:02x}", b);
+                        s
+                    })
+            });
+        Self {
+            client_ip,
+            route: route.to_string(),
+            request_id,
+            host,
+            rate_limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Middleware {
+    Headers {
+        request: HeaderRules,
+        response: HeaderRules,
+    },
+    Path(PathRewrite),
+    RateLimit(RateLimiter),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chain(Vec<Middleware>);
+
+impl TryFrom<&[IngressRouteMiddleware]> for Chain {
+    type Error = Error;
+
+    fn try_from(specs: &[IngressRouteMiddleware]) -> Result<Self, Self::Error> {
+        let mut middlewares = Vec::with_capacity(specs.len());
+        for spec in specs {
+            if let Some(headers) = &spec.headers {
+                middlewares.push(Middleware::Headers {
+                    request: headers
+                        .request
+                        .as_ref()
+                        .map(HeaderRules::try_from)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    response: headers
+                        .response
+                        .as_ref()
+                        .map(HeaderRules::try_from)
+                        .transpose()?
+                        .unwrap_or_default(),
+                });
+            }
+            if let Some(prefixes) = &spec.strip_prefix {
+                middlewares.push(Middleware::Path(PathRewrite::StripPrefix(prefixes.clone())));
+            }
+            if let Some(prefix) = &spec.add_prefix {
+                middlewares.push(Middleware::Path(PathRewrite::AddPrefix(Template::parse(
+                    prefix,
+                )?)));
+            }
+            if let Some(replace) = &spec.replace_path_regex {
+                middlewares.push(Middleware::Path(PathRewrite::try_from(replace)?));
+            }
+            if let Some(rate_limit) = &spec.rate_limit {
+                middlewares.push(Middleware::RateLimit(RateLimiter::try_from(rate_limit)?));
+            }
+        }
+        Ok(Self(middlewares))
+    }
+}
+
+impl Chain {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn inherit(&mut self, previous: &Chain) {
+        for (middleware, old) in self.0.iter_mut().zip(&previous.0) {
+            if let (Middleware::RateLimit(limiter), Middleware::RateLimit(old)) = (middleware, old)
+            {
+                limiter.inherit(old);
+            }
+        }
+    }
+
+    pub fn request_filter(
+        &self,
+        req: &RequestHeader,
+        variables: &mut Variables,
+    ) -> pingora::Result<Option<ResponseHeader>> {
+        for middleware in &self.0 {
+            if let Middleware::RateLimit(limiter) = middleware {
+                match limiter.check(req, variables) {
+                    Ok(status) => variables.rate_limit = Some(status),
+                    Err(retry_after) => {
+                        let mut resp =
+                            ResponseHeader::build(StatusCode::TOO_MANY_REQUESTS, Some(5))?;
+                        resp.insert_header("retry-after", retry_after.to_string())?;
+                        rate_limit::Status {
+                            limit: limiter.limit(),
+                            remaining: 0,
+                            reset: retry_after,
+                        }
+                        .apply(&mut resp)?;
+                        resp.insert_header("content-length", "0")?;
+                        return Ok(Some(resp));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn upstream_request(
+        &self,
+        req: &mut RequestHeader,
+        variables: &Variables,
+    ) -> pingora::Result<()> {
+        let original = req.uri.clone();
+        let mut stripped = String::new();
+        for middleware in &self.0 {
+            match middleware {
+                Middleware::Headers { request, .. } => request.apply(req, variables)?,
+                Middleware::RateLimit(_) => {}
+                Middleware::Path(rewrite) => {
+                    if let Some((path, prefix)) = rewrite.rewrite(req.uri.path(), variables) {
+                        path::set_path(req, &path)?;
+                        stripped.push_str(prefix.unwrap_or_default());
+                    }
+                }
+            }
+        }
+
+        if req.uri != original {
+            let uri = original
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or(original.path());
+            req.insert_header(ORIGINAL_URI_HEADER, uri)?;
+        }
+        if !stripped.is_empty() {
+            req.insert_header(FORWARDED_PREFIX_HEADER, stripped)?;
+        }
+        Ok(())
+    }
+
+    pub fn response(
+        &self,
+        resp: &mut ResponseHeader,
+        variables: &Variables,
+    ) -> pingora::Result<()> {
+        for middleware in &self.0 {
+            match middleware {
+                Middleware::Headers { response, .. } => response.apply(resp, variables)?,
+                Middleware::Path(_) | Middleware::RateLimit(_) => {}
+            }
+        }
+        if let Some(status) = &variables.rate_limit {
+            status.apply(resp)?;
+        }
+        Ok(())
+    }
+}
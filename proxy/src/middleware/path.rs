@@ -0,0 +1,149 @@
+use crate::middleware::template::Template;
+use crate::middleware::{Error, Variables};
+use crds::IngressRouteReplacePathRegex;
+use pingora::http::RequestHeader;
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub enum PathRewrite {
+    StripPrefix(Vec<String>),
+    AddPrefix(Template),
+    ReplaceRegex(Regex, Template),
+}
+
+impl TryFrom<&IngressRouteReplacePathRegex> for PathRewrite {
+    type Error = Error;
+
+    fn try_from(spec: &IngressRouteReplacePathRegex) -> Result<Self, Self::Error> {
+        let regex =
+            Regex::new(&spec.regex).map_err(|e| Error::InvalidRegex(spec.regex.clone(), e))?;
+        Ok(PathRewrite::ReplaceRegex(
+            regex,
+            Template::parse_lenient(&spec.replacement)?,
+        ))
+    }
+}
+
+impl PathRewrite {
+    pub fn rewrite<'a>(
+        &'a self,
+        path: &str,
+        variables: &Variables,
+    ) -> Option<(String, Option<&'a str>)> {
+        match self {
+            PathRewrite::StripPrefix(prefixes) => prefixes.iter().find_map(|prefix| {
+                let rest = path.strip_prefix(prefix.as_str())?;
+                let path = match rest.starts_with('/') {
+                    true => rest.to_string(),
+                    false => format!("/{}", rest),
+                };
+                Some((path, Some(prefix.trim_end_matches('/'))))
+            }),
+            PathRewrite::AddPrefix(prefix) => {
+                let prefix = prefix.render(variables);
+                Some((format!("{}{}", prefix.trim_end_matches('/'), path), None))
+            }
+            PathRewrite::ReplaceRegex(regex, replacement) => regex.is_match(path).then(|| {
+                let replacement = replacement.render(variables);
+                (regex.replace(path, replacement.as_str()).into_owned(), None)
+            }),
+        }
+    }
+}
+
+pub fn set_path(req: &mut RequestHeader, path: &str) -> pingora::Result<()> {
+    let uri = match req.uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    let uri = uri.parse().map_err(|e| {
+        pingora::Error::because(
+            pingora::ErrorType::InternalError,
+            "Invalid rewritten request path",
+            e,
+        )
+    })?;
+    req.set_uri(uri);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::middleware::{Chain, Variables};
+    use crds::{IngressRouteMiddleware, IngressRouteReplacePathRegex};
+    use pingora::http::RequestHeader;
+    use std::collections::BTreeMap;
+
+    fn rewrite(middleware: IngressRouteMiddleware, uri: &str) -> RequestHeader {
+        let chain = Chain::try_from([middleware].as_slice()).unwrap();
+        let mut req = RequestHeader::build("GET", uri.as_bytes(), None).unwrap();
+        let host = BTreeMap::from([("1".to_string(), "a".to_string())]);
+        let variables = Variables::new(&req, None, "default/web", host);
+        chain.upstream_request(&mut req, &variables).unwrap();
+        req
+    }
+
+    fn header<'a>(req: &'a RequestHeader, name: &str) -> Option<&'a str> {
+        req.headers.get(name).map(|v| v.to_str().unwrap())
+    }
+
+    fn replace(regex: &str, replacement: &str) -> IngressRouteMiddleware {
+        IngressRouteMiddleware {
+            replace_path_regex: Some(IngressRouteReplacePathRegex {
+                regex: regex.into(),
+                replacement: replacement.into(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn strips_the_first_matching_prefix() {
+        let strip = || IngressRouteMiddleware {
+            strip_prefix: Some(vec!["/api/".into(), "/v1".into()]),
+            ..Default::default()
+        };
+        let req = rewrite(strip(), "/api/users?page=2");
+        assert_eq!(req.uri, "/users?page=2");
+        assert_eq!(header(&req, "x-forwarded-prefix"), Some("/api"));
+        assert_eq!(header(&req, "x-original-uri"), Some("/api/users?page=2"));
+
+        assert_eq!(rewrite(strip(), "/v1").uri, "/");
+
+        let req = rewrite(strip(), "/other?page=2");
+        assert_eq!(req.uri, "/other?page=2");
+        assert_eq!(header(&req, "x-forwarded-prefix"), None);
+        assert_eq!(header(&req, "x-original-uri"), None);
+    }
+
+    #[test]
+    fn adds_a_templated_prefix() {
+        let add = IngressRouteMiddleware {
+            add_prefix: Some("/tenants/${host.1}/".into()),
+            ..Default::default()
+        };
+        let req = rewrite(add, "/users?page=2");
+        assert_eq!(req.uri, "/tenants/a/users?page=2");
+        assert_eq!(header(&req, "x-original-uri"), Some("/users?page=2"));
+        assert_eq!(header(&req, "x-forwarded-prefix"), None);
+    }
+
+    #[test]
+    fn replaces_the_path_with_regex_captures() {
+        let req = rewrite(
+            replace("^/old/(.*)$", "/new/${1}/${route}"),
+            "/old/users?page=2",
+        );
+        assert_eq!(req.uri, "/new/users/default/web?page=2");
+        assert_eq!(header(&req, "x-original-uri"), Some("/old/users?page=2"));
+
+        let req = rewrite(replace("^/old/(.*)$", "/new/${1}"), "/current");
+        assert_eq!(req.uri, "/current");
+        assert_eq!(header(&req, "x-original-uri"), None);
+    }
+
+    #[test]
+    fn rejects_invalid_regex() {
+        assert!(Chain::try_from([replace("(", "/")].as_slice()).is_err());
+    }
+}
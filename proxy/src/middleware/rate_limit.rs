@@ -0,0 +1,236 @@
+use crate::middleware::{Error, Variables};
+use crds::{IngressRouteRateLimit, RateLimitSource};
+use dashmap::DashMap;
+use pingora::http::{RequestHeader, ResponseHeader};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+// Set by an authenticating proxy in front of ferrix, such as oauth2-proxy.
+const DEFAULT_USER_HEADER: &str = "x-forwarded-user";
+const MAX_BUCKETS: usize = 100_000;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+// Keys seen once the table is full share this bucket until a sweep frees space.
+const OVERFLOW_KEY: &str = "";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Key {
+    ClientIp,
+    Header(String),
+    User(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: u64,
+}
+
+impl Status {
+    pub fn apply(&self, resp: &mut ResponseHeader) -> pingora::Result<()> {
+        resp.insert_header("x-ratelimit-limit", self.limit.to_string())?;
+        resp.insert_header("x-ratelimit-remaining", self.remaining.to_string())?;
+        resp.insert_header("x-ratelimit-reset", self.reset.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: u32,
+    key: Key,
+    buckets: Arc<DashMap<String, Bucket>>,
+}
+
+impl TryFrom<&IngressRouteRateLimit> for RateLimiter {
+    type Error = Error;
+
+    fn try_from(spec: &IngressRouteRateLimit) -> Result<Self, Self::Error> {
+        if spec.average == 0 {
+            return Err(Error::InvalidRateLimit(
+                "average must be greater than 0".into(),
+            ));
+        }
+        let key = match spec.source {
+            RateLimitSource::ClientIp => Key::ClientIp,
+            RateLimitSource::User => Key::User(
+                spec.header_name
+                    .clone()
+                    .unwrap_or(DEFAULT_USER_HEADER.to_string()),
+            ),
+            RateLimitSource::Header => Key::Header(spec.header_name.clone().ok_or(
+                Error::InvalidRateLimit("header source requires headerName".into()),
+            )?),
+        };
+        let limiter = Self {
+            rate: spec.average as f64,
+            burst: spec.burst.unwrap_or(spec.average).max(1),
+            key,
+            buckets: Arc::new(DashMap::new()),
+        };
+        tokio::spawn(sweep(
+            Arc::downgrade(&limiter.buckets),
+            limiter.rate,
+            limiter.burst as f64,
+        ));
+        Ok(limiter)
+    }
+}
+
+// Drops buckets that have refilled completely, as a fresh bucket would behave the same.
+async fn sweep(buckets: Weak<DashMap<String, Bucket>>, rate: f64, burst: f64) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(buckets) = buckets.upgrade() else {
+            break;
+        };
+        let now = Instant::now();
+        buckets.retain(|_, bucket| bucket.refill(now, rate, burst) < burst);
+    }
+}
+
+impl RateLimiter {
+    pub fn check(&self, req: &RequestHeader, variables: &Variables) -> Result<Status, u64> {
+        let mut key = self.key(req, variables);
+        let now = Instant::now();
+        let burst = self.burst as f64;
+        if self.buckets.len() >= MAX_BUCKETS && !self.buckets.contains_key(&key) {
+            key = OVERFLOW_KEY.to_string();
+        }
+
+        let mut bucket = self.buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let tokens = bucket.refill(now, self.rate, burst);
+        if tokens < 1.0 {
+            return Err(((1.0 - tokens) / self.rate).ceil() as u64);
+        }
+
+        bucket.tokens = tokens - 1.0;
+        Ok(Status {
+            limit: self.burst,
+            remaining: bucket.tokens as u32,
+            reset: ((burst - bucket.tokens) / self.rate).ceil() as u64,
+        })
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.burst
+    }
+
+    // Keeps the buckets of an unchanged limiter from the previous route table.
+    pub fn inherit(&mut self, previous: &RateLimiter) {
+        if self.rate == previous.rate && self.burst == previous.burst && self.key == previous.key {
+            self.buckets = previous.buckets.clone();
+        }
+    }
+
+    fn key(&self, req: &RequestHeader, variables: &Variables) -> String {
+        let key = match &self.key {
+            Key::ClientIp => None,
+            Key::Header(name) | Key::User(name) => req
+                .headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        };
+        key.or_else(|| variables.client_ip.map(|ip| ip.to_string()))
+            .unwrap_or_default()
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+        self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bucket, RateLimiter, MAX_BUCKETS};
+    use crate::middleware::Variables;
+    use crds::{IngressRouteRateLimit, RateLimitSource};
+    use pingora::http::RequestHeader;
+    use std::collections::BTreeMap;
+    use std::time::Instant;
+
+    fn limiter(source: RateLimitSource, average: u32) -> RateLimiter {
+        RateLimiter::try_from(&IngressRouteRateLimit {
+            average,
+            burst: Some(1),
+            source,
+            header_name: None,
+        })
+        .unwrap()
+    }
+
+    fn request(client_ip: &str, user: Option<&str>) -> (RequestHeader, Variables) {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        if let Some(user) = user {
+            req.insert_header("x-forwarded-user", user).unwrap();
+        }
+        let variables = Variables::new(&req, client_ip.parse().ok(), "r", BTreeMap::new());
+        (req, variables)
+    }
+
+    #[tokio::test]
+    async fn user_source_keys_on_the_forwarded_user() {
+        let limiter = limiter(RateLimitSource::User, 1);
+        let (req, variables) = request("10.0.0.1", None);
+        assert!(limiter.check(&req, &variables).is_ok());
+        let (req, variables) = request("10.0.0.2", None);
+        assert!(limiter.check(&req, &variables).is_ok());
+        assert!(limiter.buckets.contains_key("10.0.0.1"));
+
+        let (req, variables) = request("10.0.0.1", Some("alice"));
+        assert!(limiter.check(&req, &variables).is_ok());
+        let (req, variables) = request("10.0.0.3", Some("alice"));
+        assert!(limiter.check(&req, &variables).is_err());
+    }
+
+    #[tokio::test]
+    async fn new_keys_share_one_bucket_once_full() {
+        let limiter = limiter(RateLimitSource::ClientIp, 1);
+        let now = Instant::now();
+        for i in 0..MAX_BUCKETS {
+            limiter.buckets.insert(
+                i.to_string(),
+                Bucket {
+                    tokens: 0.0,
+                    updated: now,
+                },
+            );
+        }
+        let (req, variables) = request("10.0.0.1", None);
+        assert!(limiter.check(&req, &variables).is_ok());
+        let (req, variables) = request("10.0.0.2", None);
+        assert!(limiter.check(&req, &variables).is_err());
+        assert_eq!(limiter.buckets.len(), MAX_BUCKETS + 1);
+    }
+
+    #[tokio::test]
+    async fn unchanged_limiter_keeps_buckets_across_rebuilds() {
+        let old = limiter(RateLimitSource::ClientIp, 1);
+        let (req, variables) = request("10.0.0.1", None);
+        assert!(old.check(&req, &variables).is_ok());
+
+        let mut new = limiter(RateLimitSource::ClientIp, 1);
+        new.inherit(&old);
+        assert!(new.check(&req, &variables).is_err());
+
+        let mut changed = limiter(RateLimitSource::ClientIp, 2);
+        changed.inherit(&old);
+        assert!(changed.check(&req, &variables).is_ok());
+    }
+}
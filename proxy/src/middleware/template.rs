@@ -0,0 +1,146 @@
+use crate::middleware::{Error, Variables};
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    ClientIp,
+    Route,
+    RequestId,
+    Host(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Template(Vec<Segment>);
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        Self::parse_with(input, false)
+    }
+
+    // Unknown variables are kept as literals, e.g. `${1}` in a regex replacement.
+    pub fn parse_lenient(input: &str) -> Result<Self, Error> {
+        Self::parse_with(input, true)
+    }
+
+    fn parse_with(input: &str, lenient: bool) -> Result<Self, Error> {
+        let mut segments = Vec::new();
+        let mut rest = input;
+        while let Some(start) = rest.find("${") {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| Error::UnterminatedVariable(input.to_string()))?;
+            let segment = match rest[start + 2..start + end].trim() {
+                "client_ip" => Segment::ClientIp,
+                "route" => Segment::Route,
+                "request_id" => Segment::RequestId,
+                "host" => Segment::Host("0".to_string()),
+                name => match name.strip_prefix("host.") {
+                    Some(group) => Segment::Host(group.to_string()),
+                    None if lenient => Segment::Literal(rest[start..start + end + 1].to_string()),
+                    None => return Err(Error::UnknownVariable(name.to_string())),
+                },
+            };
+            segments.push(segment);
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(Self(segments))
+    }
+
+    pub fn render(&self, variables: &Variables) -> String {
+        self.0.iter().fold(String::new(), |mut s, segment| {
+            match segment {
+                Segment::Literal(l) => s.push_str(l),
+                Segment::ClientIp => {
+                    if let Some(ip) = variables.client_ip {
+                        s.push_str(&ip.to_string());
+                    }
+                }
+                Segment::Route => s.push_str(&variables.route),
+                Segment::RequestId => s.push_str(&variables.request_id),
+                Segment::Host(group) => {
+                    if let Some(value) = variables.host.get(group) {
+                        s.push_str(value);
+                    }
+                }
+            }
+            s
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+    use crate::middleware::Variables;
+    use pingora::http::RequestHeader;
+    use std::collections::BTreeMap;
+
+    fn variables() -> Variables {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        req.insert_header("x-request-id", "abc").unwrap();
+        let host = BTreeMap::from([
+            ("0".to_string(), "tenant-a.example.com".to_string()),
+            ("1".to_string(), "a".to_string()),
+            ("name".to_string(), "tenant".to_string()),
+        ]);
+        Variables::new(&req, "10.0.0.1".parse().ok(), "default/web", host)
+    }
+
+    fn render(input: &str) -> String {
+        Template::parse(input).unwrap().render(&variables())
+    }
+
+    #[test]
+    fn renders_variables_between_literals() {
+        assert_eq!(render("plain"), "plain");
+        assert_eq!(render(""), "");
+        assert_eq!(
+            render("ip=${client_ip};route=${route};id=${request_id}"),
+            "ip=10.0.0.1;route=default/web;id=abc"
+        );
+        assert_eq!(render("${ route }${route}"), "default/webdefault/web");
+    }
+
+    #[test]
+    fn renders_host_captures() {
+        assert_eq!(render("${host}"), "tenant-a.example.com");
+        assert_eq!(render("/${host.1}/${host.name}"), "/a/tenant");
+        assert_eq!(render("[${host.2}]"), "[]");
+    }
+
+    #[test]
+    fn missing_client_ip_renders_empty() {
+        let mut variables = variables();
+        variables.client_ip = None;
+        let template = Template::parse("<${client_ip}>").unwrap();
+        assert_eq!(template.render(&variables), "<>");
+    }
+
+    #[test]
+    fn rejects_unknown_and_unterminated_variables() {
+        assert_eq!(
+            Template::parse("${nope}").unwrap_err().to_string(),
+            "unknown template variable 'nope'"
+        );
+        assert_eq!(
+            Template::parse("a${route").unwrap_err().to_string(),
+            "unterminated variable in template 'a${route'"
+        );
+    }
+
+    #[test]
+    fn lenient_keeps_unknown_variables_as_literals() {
+        let template = Template::parse_lenient("/v2/${1}/${route}/${name}").unwrap();
+        assert_eq!(
+            template.render(&variables()),
+            "/v2/${1}/default/web/${name}"
+        );
+        assert!(Template::parse_lenient("${1").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::load_balancer::Timeouts;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -5,5 +6,28 @@ pub struct Config {
     pub name: String,
     pub port: u16,
     #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default)]
     pub secure: bool,
+    #[serde(default)]
+    pub h2c: bool,
+    pub default_certificate: Option<Certificate>,
+    pub redirect_to: Option<String>,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Http,
+    Tcp,
+    Udp,
+}
+
+#[derive(Deserialize)]
+pub struct Certificate {
+    pub cert_file: String,
+    pub key_file: String,
 }
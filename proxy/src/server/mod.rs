@@ -1,6 +1,8 @@
 pub mod config;
 mod entry_point;
 
+pub use entry_point::Protocol;
+
 use pingora::server;
 use pingora::server::Server;
 use serde::Deserialize;
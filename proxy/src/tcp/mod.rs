@@ -0,0 +1,335 @@
+mod sni;
+
+use crate::k8s;
+use crate::k8s::claim::{self, Claim};
+use crate::load_balancer::StreamLoadBalancer;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use crds::IngressRouteTCP;
+use dashmap::DashMap;
+use futures_util::future::BoxFuture;
+use k8s_openapi::api::core::v1::Endpoints;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{Api, Resource};
+use log::{debug, error, warn};
+use pingora::apps::ServerApp;
+use pingora::protocols::Stream;
+use pingora::server::ShutdownWatch;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio::time::timeout;
+
+const CLIENT_HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type RouteTable = Arc<DashMap<Option<String>, Route>>;
+
+#[derive(Clone)]
+pub struct Route {
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub load_balancer: StreamLoadBalancer,
+}
+
+impl Claim for Route {
+    fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+struct ManagedRoute {
+    sni: Option<String>,
+    route: Route,
+    watch: Arc<Notify>,
+}
+
+#[derive(Clone)]
+pub struct SharedRouter(Arc<Router>);
+
+impl SharedRouter {
+    pub fn new(router: Router) -> Self {
+        Self(Arc::new(router))
+    }
+}
+
+#[async_trait]
+impl ServerApp for SharedRouter {
+    async fn process_new(
+        self: &Arc<Self>,
+        mut session: Stream,
+        _shutdown: &ShutdownWatch,
+    ) -> Option<Stream> {
+        if let Err(e) = self.0.proxy(&mut session).await {
+            debug!("TCP connection closed: {}", e);
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct Router {
+    route_table: RouteTable,
+    managed_objects: Arc<DashMap<String, ManagedRoute>>,
+}
+
+impl Router {
+    pub fn update_route_tables(
+        route_tables: Arc<DashMap<String, SharedRouter>>,
+    ) -> impl Fn(
+        kube::client::Client,
+        IngressRouteTCP,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>>
+           + Send
+           + Sync
+           + 'static {
+        move |k8s_client, route| {
+            let route_tables = route_tables.clone();
+            Box::pin(async move {
+                if let Some(router) = route_tables
+                    .get(&route.spec.entrypoint)
+                    .map(|v| v.value().clone())
+                {
+                    return router.0.update_route_table(k8s_client, route).await;
+                }
+
+                Ok(())
+            })
+        }
+    }
+
+    pub fn delete_routes(
+        route_tables: Arc<DashMap<String, SharedRouter>>,
+    ) -> impl Fn(
+        kube::client::Client,
+        IngressRouteTCP,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>>
+           + Send
+           + Sync
+           + 'static {
+        move |_, route| {
+            if let Some(route_id) = route.meta().uid.as_deref() {
+                route_tables
+                    .iter()
+                    .for_each(|router| router.0.delete_route(route_id));
+            }
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn update_route_table(
+        &self,
+        k8s_client: kube::Client,
+        route: IngressRouteTCP,
+    ) -> Result<(), anyhow::Error> {
+        let route_namespace = route.meta().namespace.clone().unwrap();
+        let service = &route.spec.route.service;
+        let namespace = service.namespace.clone().unwrap_or(route_namespace);
+        let api = Api::<Endpoints>::namespaced(k8s_client.clone(), &namespace);
+        let ep = api.get(&service.name).await.map_err(|e| {
+            anyhow!(
+                "unable to get endpoints for service {}: {}",
+                service.name,
+                e
+            )
+        })?;
+        let load_balancer =
+            StreamLoadBalancer::new(k8s::endpoints::get_addresses(ep, service.port))?;
+
+        let watch = Arc::new(Notify::new());
+        let lb = load_balancer.clone();
+        k8s::endpoints::watch(
+            k8s_client,
+            namespace,
+            service.name.clone(),
+            service.port,
+            watch.clone(),
+            move |addresses| match lb.update(addresses) {
+                Ok(_) => debug!("TCP load balancer updated with new endpoint addresses"),
+                Err(e) => error!(
+                    "Unable to update TCP load balancer with new endpoints: {}",
+                    e
+                ),
+            },
+        );
+
+        self.apply(&route, load_balancer, watch);
+        Ok(())
+    }
+
+    fn apply(
+        &self,
+        route: &IngressRouteTCP,
+        load_balancer: StreamLoadBalancer,
+        watch: Arc<Notify>,
+    ) {
+        let route_meta = route.meta();
+        let route_id = route_meta.uid.clone().unwrap();
+        let sni = route.spec.route.sni.as_ref().map(|sni| sni.to_lowercase());
+        let object = ManagedRoute {
+            sni: sni.clone(),
+            route: Route {
+                name: format!(
+                    "{}/{}",
+                    route_meta.namespace.as_deref().unwrap_or_default(),
+                    route_meta.name.as_deref().unwrap_or_default()
+                ),
+                created: claim::created(route),
+                load_balancer,
+            },
+            watch,
+        };
+        if let Some(previous) = self.managed_objects.insert(route_id, object) {
+            previous.watch.notify_one();
+            if previous.sni != sni {
+                self.resolve(&previous.sni);
+            }
+        }
+        self.resolve(&sni);
+    }
+
+    fn delete_route(&self, route_id: &str) {
+        if let Some((_, object)) = self.managed_objects.remove(route_id) {
+            object.watch.notify_one();
+            self.resolve(&object.sni);
+        }
+    }
+
+    fn resolve(&self, sni: &Option<String>) {
+        let claims = self
+            .managed_objects
+            .iter()
+            .filter(|object| object.sni == *sni)
+            .map(|object| object.route.clone())
+            .collect();
+        let Some((route, ignored)) = claim::oldest(claims) else {
+            self.route_table.remove(sni);
+            return;
+        };
+        for ignored in ignored {
+            warn!(
+                "Ignoring IngressRouteTCP {}: SNI {} is already routed by {}",
+                ignored.name,
+                sni.as_deref().unwrap_or("catch-all"),
+                route.name
+            );
+        }
+        self.route_table.insert(sni.clone(), route);
+    }
+
+    async fn proxy(&self, downstream: &mut Stream) -> Result<(), anyhow::Error> {
+        let mut client_hello = Vec::new();
+        let sni = match self.route_table.iter().any(|route| route.key().is_some()) {
+            true => timeout(
+                CLIENT_HELLO_TIMEOUT,
+                sni::read(downstream, &mut client_hello),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                debug!("Timed out reading TLS client hello, using the catch-all route");
+                Ok(None)
+            })?,
+            false => None,
+        };
+        let route = sni
+            .and_then(|sni| self.route_table.get(&Some(sni)))
+            .or_else(|| self.route_table.get(&None))
+            .map(|route| route.value().clone())
+            .ok_or_else(|| anyhow!("no TCP route matches connection"))?;
+
+        let mut tried = Vec::new();
+        let mut upstream = loop {
+            let backend = route
+                .load_balancer
+                .select(&tried)
+                .ok_or_else(|| anyhow!("no upstream endpoints available for {}", route.name))?;
+            let Some(addr) = backend.addr.as_inet() else {
+                tried.push(backend.addr);
+                continue;
+            };
+            match timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+                Ok(Ok(stream)) => break stream,
+                Ok(Err(e)) => warn!("Unable to connect to {} for {}: {}", addr, route.name, e),
+                Err(_) => warn!("Timed out connecting to {} for {}", addr, route.name),
+            }
+            tried.push(backend.addr);
+        };
+
+        upstream.write_all(&client_hello).await?;
+        tokio::io::copy_bidirectional(downstream, &mut upstream).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Router, SharedRouter};
+    use crate::k8s::testing;
+    use crate::load_balancer::StreamLoadBalancer;
+    use crds::IngressRouteTCP;
+    use dashmap::DashMap;
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    fn apply(router: &Router, name: &str, created: &str, sni: Option<&str>) -> IngressRouteTCP {
+        let route: IngressRouteTCP = testing::resource(
+            "IngressRouteTCP",
+            name,
+            created,
+            serde_json::json!({
+                "entrypoint": "db",
+                "route": {"sni": sni, "service": {"name": "db", "port": 5432}},
+            }),
+        );
+        let load_balancer = StreamLoadBalancer::new(Vec::new()).unwrap();
+        router.apply(&route, load_balancer, Arc::new(Notify::new()));
+        route
+    }
+
+    fn served(router: &Router, sni: Option<&str>) -> Option<String> {
+        router
+            .route_table
+            .get(&sni.map(str::to_string))
+            .map(|route| route.name.clone())
+    }
+
+    #[tokio::test]
+    async fn deleted_route_hands_its_sni_to_the_next_claim() {
+        let router = SharedRouter::new(Router::default());
+        let route_tables = Arc::new(DashMap::new());
+        route_tables.insert("db".to_string(), router.clone());
+        let old = apply(
+            &router.0,
+            "old",
+            "2024-01-01T00:00:00Z",
+            Some("DB.example.com"),
+        );
+        let new = apply(
+            &router.0,
+            "new",
+            "2024-01-02T00:00:00Z",
+            Some("db.example.com"),
+        );
+        apply(&router.0, "other", "2024-01-03T00:00:00Z", None);
+        assert_eq!(
+            served(&router.0, Some("db.example.com")).as_deref(),
+            Some("default/old")
+        );
+
+        let delete = Router::delete_routes(route_tables);
+        delete(testing::client(), old).await.unwrap();
+        assert_eq!(
+            served(&router.0, Some("db.example.com")).as_deref(),
+            Some("default/new")
+        );
+        delete(testing::client(), new).await.unwrap();
+        assert_eq!(served(&router.0, Some("db.example.com")), None);
+        assert_eq!(served(&router.0, None).as_deref(), Some("default/other"));
+    }
+}
@@ -0,0 +1,196 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const HANDSHAKE: u8 = 0x16;
+const CLIENT_HELLO: u8 = 0x01;
+const SERVER_NAME: u16 = 0x0000;
+const HOST_NAME: u8 = 0x00;
+const MAX_CLIENT_HELLO: usize = 16 * 1024;
+
+enum ClientHello {
+    Incomplete,
+    ServerName(Option<String>),
+}
+
+pub async fn read<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<Option<String>> {
+    loop {
+        if let ClientHello::ServerName(name) = parse(buf) {
+            return Ok(name);
+        }
+        if buf.len() >= MAX_CLIENT_HELLO || stream.read_buf(buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+fn parse(buf: &[u8]) -> ClientHello {
+    if buf.len() < 5 {
+        return ClientHello::Incomplete;
+    }
+    if buf[0] != HANDSHAKE {
+        return ClientHello::ServerName(None);
+    }
+    let len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+    match buf.get(5..5 + len) {
+        Some(record) => ClientHello::ServerName(server_name(record)),
+        None => ClientHello::Incomplete,
+    }
+}
+
+fn server_name(record: &[u8]) -> Option<String> {
+    let mut hello = Reader(record);
+    if hello.u8()? != CLIENT_HELLO {
+        return None;
+    }
+    // handshake length, client version and random
+    hello.take(3 + 2 + 32)?;
+    let session_id = hello.u8()? as usize;
+    hello.take(session_id)?;
+    let cipher_suites = hello.u16()? as usize;
+    hello.take(cipher_suites)?;
+    let compression_methods = hello.u8()? as usize;
+    hello.take(compression_methods)?;
+
+    let len = hello.u16()? as usize;
+    let mut extensions = Reader(hello.take(len)?);
+    while let (Some(kind), Some(len)) = (extensions.u16(), extensions.u16()) {
+        let data = extensions.take(len as usize)?;
+        if kind != SERVER_NAME {
+            continue;
+        }
+
+        let mut names = Reader(data);
+        let len = names.u16()? as usize;
+        let mut names = Reader(names.take(len)?);
+        while let Some(kind) = names.u8() {
+            let len = names.u16()? as usize;
+            let name = names.take(len)?;
+            if kind == HOST_NAME {
+                return std::str::from_utf8(name).ok().map(str::to_lowercase);
+            }
+        }
+    }
+    None
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(data)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, read, ClientHello};
+    use tokio::io::AsyncReadExt;
+
+    fn extension(kind: u16, data: &[u8]) -> Vec<u8> {
+        let mut ext = kind.to_be_bytes().to_vec();
+        ext.extend((data.len() as u16).to_be_bytes());
+        ext.extend(data);
+        ext
+    }
+
+    fn client_hello(server_name: Option<&str>) -> Vec<u8> {
+        let mut extensions = extension(0x002b, &[0x02, 0x03, 0x04]);
+        if let Some(name) = server_name {
+            let mut entry = vec![0x00];
+            entry.extend((name.len() as u16).to_be_bytes());
+            entry.extend(name.as_bytes());
+            let mut list = (entry.len() as u16).to_be_bytes().to_vec();
+            list.extend(entry);
+            extensions.extend(extension(0x0000, &list));
+        }
+
+        let mut body = vec![0x03, 0x03];
+        body.extend([0; 32]);
+        body.extend([0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        body.extend((extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+
+        let mut handshake = vec![0x01];
+        handshake.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend((handshake.len() as u16).to_be_bytes());
+        record.extend(handshake);
+        record
+    }
+
+    async fn read_all(data: &[u8]) -> Option<String> {
+        let mut buf = Vec::new();
+        let name = read(&mut &data[..], &mut buf).await.unwrap();
+        assert_eq!(buf, data);
+        name
+    }
+
+    #[tokio::test]
+    async fn reads_server_name() {
+        let hello = client_hello(Some("DB.Example.com"));
+        assert_eq!(read_all(&hello).await.as_deref(), Some("db.example.com"));
+    }
+
+    #[tokio::test]
+    async fn missing_server_name_extension() {
+        assert_eq!(read_all(&client_hello(None)).await, None);
+    }
+
+    #[tokio::test]
+    async fn truncated_record() {
+        let hello = client_hello(Some("db.example.com"));
+        for len in [0, 3, 5, hello.len() - 1] {
+            assert!(matches!(parse(&hello[..len]), ClientHello::Incomplete));
+            assert_eq!(read_all(&hello[..len]).await, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn record_split_across_reads() {
+        let hello = client_hello(Some("db.example.com"));
+        for split in [1, 5, 20, hello.len() - 1] {
+            let (first, second) = hello.split_at(split);
+            let mut stream = first.chain(second);
+            let mut buf = Vec::new();
+            let name = read(&mut stream, &mut buf).await.unwrap();
+            assert_eq!(
+                name.as_deref(),
+                Some("db.example.com"),
+                "split at {}",
+                split
+            );
+            assert_eq!(buf, hello);
+        }
+    }
+
+    #[tokio::test]
+    async fn non_tls_first_packet() {
+        assert_eq!(read_all(b"PING\r\n").await, None);
+    }
+
+    #[test]
+    fn malformed_extensions() {
+        let mut hello = client_hello(Some("db.example.com"));
+        // Claim the server name list is longer than the extension.
+        let at = hello.len() - "db.example.com".len() - 5;
+        hello[at] = 0xff;
+        assert!(matches!(parse(&hello), ClientHello::ServerName(None)));
+    }
+}
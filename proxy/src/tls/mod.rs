@@ -0,0 +1,205 @@
+mod upstream;
+
+pub use upstream::UpstreamTls;
+
+use crate::gateway::host;
+use crate::gateway::RouteTable;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{debug, error, warn};
+use pingora::listeners::TlsAccept;
+use pingora::protocols::tls::TlsRef;
+use pingora::tls::error::ErrorStack;
+use pingora::tls::ext;
+use pingora::tls::pkey::{PKey, Private};
+use pingora::tls::ssl::NameType;
+use pingora::tls::x509::X509;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub type CertificateStore = Arc<DashMap<String, Arc<Certificate>>>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("unable to read certificate file: {0}")]
+    IO(std::io::Error),
+
+    #[error("no certificate found in PEM data")]
+    MissingCertificate,
+
+    #[error("unable to parse certificate: {0}")]
+    Certificate(ErrorStack),
+
+    #[error("unable to parse private key: {0}")]
+    PrivateKey(ErrorStack),
+}
+
+pub struct Certificate {
+    leaf: X509,
+    chain: Vec<X509>,
+    key: PKey<Private>,
+}
+
+impl Certificate {
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let mut certs = X509::stack_from_pem(cert)
+            .map_err(Error::Certificate)?
+            .into_iter();
+        let leaf = certs.next().ok_or(Error::MissingCertificate)?;
+        let key = PKey::private_key_from_pem(key).map_err(Error::PrivateKey)?;
+        Ok(Self {
+            leaf,
+            chain: certs.collect(),
+            key,
+        })
+    }
+
+    pub fn from_files(cert_file: &str, key_file: &str) -> Result<Self, Error> {
+        let cert = std::fs::read(cert_file).map_err(Error::IO)?;
+        let key = std::fs::read(key_file).map_err(Error::IO)?;
+        Self::from_pem(&cert, &key)
+    }
+
+    fn apply(&self, ssl: &mut TlsRef) -> Result<(), ErrorStack> {
+        ext::ssl_use_certificate(ssl, &self.leaf)?;
+        for cert in &self.chain {
+            ext::ssl_add_chain_cert(ssl, cert)?;
+        }
+        ext::ssl_use_private_key(ssl, &self.key)
+    }
+}
+
+pub struct CertificateResolver {
+    certificates: CertificateStore,
+    route_table: RouteTable,
+    default: Option<Arc<Certificate>>,
+}
+
+impl CertificateResolver {
+    pub fn new(
+        certificates: CertificateStore,
+        route_table: RouteTable,
+        default: Option<Certificate>,
+    ) -> Self {
+        Self {
+            certificates,
+            route_table,
+            default: default.map(Arc::new),
+        }
+    }
+
+    // Hosts are matched like routes, but the configured default certificate is preferred over
+    // the one of the catch-all route.
+    fn resolve(&self, server_name: Option<&str>) -> Option<Arc<Certificate>> {
+        let get = |name: &str| self.certificates.get(name).map(|c| c.value().clone());
+        server_name
+            .and_then(|name| {
+                let name = name.trim_end_matches('.').to_lowercase();
+                let table = self.route_table.load();
+                host::find(&name, &table.regex_hosts, get).map(|(certificate, _)| certificate)
+            })
+            .or_else(|| self.default.clone())
+            .or_else(|| get(host::CATCH_ALL))
+    }
+}
+
+#[async_trait]
+impl TlsAccept for CertificateResolver {
+    async fn certificate_callback(&self, ssl: &mut TlsRef) -> () {
+        let server_name = ssl.servername(NameType::HOST_NAME).map(str::to_string);
+        debug!("Resolving certificate for SNI {:?}", server_name);
+
+        match self.resolve(server_name.as_deref()) {
+            Some(certificate) => {
+                if let Err(e) = certificate.apply(ssl) {
+                    error!("Unable to set certificate for SNI {:?}: {}", server_name, e);
+                }
+            }
+            None => warn!("No certificate found for SNI {:?}", server_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Certificate, CertificateResolver, CertificateStore};
+    use crate::gateway::{HostTable, RouteTable};
+    use arc_swap::ArcSwap;
+    use pingora::tls::pkey::PKey;
+    use pingora::tls::x509::X509;
+    use regex::Regex;
+    use std::sync::Arc;
+
+    fn certificate() -> Certificate {
+        let key = PKey::generate_ed25519().unwrap();
+        let mut leaf = X509::builder().unwrap();
+        leaf.set_pubkey(&key).unwrap();
+        Certificate {
+            leaf: leaf.build(),
+            chain: Vec::new(),
+            key,
+        }
+    }
+
+    struct Fixture {
+        resolver: CertificateResolver,
+        exact: Arc<Certificate>,
+        wildcard: Arc<Certificate>,
+        regex: Arc<Certificate>,
+        catch_all: Arc<Certificate>,
+    }
+
+    fn fixture(default: bool) -> Fixture {
+        let [exact, wildcard, regex, catch_all] = [(); 4].map(|_| Arc::new(certificate()));
+        let certificates = CertificateStore::default();
+        certificates.insert("a.example.com".into(), exact.clone());
+        certificates.insert("*.example.com".into(), wildcard.clone());
+        certificates.insert("tenant-(.+).example.org".into(), regex.clone());
+        certificates.insert("*".into(), catch_all.clone());
+        let pattern = "tenant-(.+).example.org";
+        let mut table = HostTable::default();
+        table.regex_hosts.push((
+            pattern.to_string(),
+            Regex::new(&format!("(?i)^(?:{})$", pattern)).unwrap(),
+        ));
+        let route_table: RouteTable = Arc::new(ArcSwap::from_pointee(table));
+        Fixture {
+            resolver: CertificateResolver::new(
+                certificates,
+                route_table,
+                default.then(certificate),
+            ),
+            exact,
+            wildcard,
+            regex,
+            catch_all,
+        }
+    }
+
+    fn resolves_to(f: &Fixture, name: Option<&str>, expected: &Arc<Certificate>) -> bool {
+        f.resolver
+            .resolve(name)
+            .is_some_and(|c| Arc::ptr_eq(&c, expected))
+    }
+
+    #[test]
+    fn follows_route_precedence() {
+        let f = fixture(true);
+        assert!(resolves_to(&f, Some("a.example.com"), &f.exact));
+        assert!(resolves_to(&f, Some("A.Example.com."), &f.exact));
+        assert!(resolves_to(&f, Some("b.example.com"), &f.wildcard));
+        assert!(resolves_to(&f, Some("tenant-x.example.org"), &f.regex));
+    }
+
+    #[test]
+    fn prefers_default_certificate_over_catch_all_route() {
+        let f = fixture(true);
+        let default = f.resolver.default.clone().unwrap();
+        assert!(resolves_to(&f, Some("other.net"), &default));
+        assert!(resolves_to(&f, None, &default));
+
+        let f = fixture(false);
+        assert!(resolves_to(&f, Some("other.net"), &f.catch_all));
+        assert!(resolves_to(&f, None, &f.catch_all));
+    }
+}
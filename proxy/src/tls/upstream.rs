@@ -0,0 +1,54 @@
+use crate::tls::Error;
+use pingora::prelude::HttpPeer;
+use pingora::tls::pkey::PKey;
+use pingora::tls::x509::X509;
+use pingora::utils::tls::CertKey;
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+pub struct UpstreamTls {
+    ca: Option<Arc<Box<[X509]>>>,
+    verify: bool,
+    server_name: Option<String>,
+    client_certificate: Option<Arc<CertKey>>,
+}
+
+impl UpstreamTls {
+    pub fn new(verify: bool, server_name: Option<String>) -> Self {
+        Self {
+            verify,
+            server_name,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_ca(mut self, pem: &[u8]) -> Result<Self, Error> {
+        let certs = X509::stack_from_pem(pem).map_err(Error::Certificate)?;
+        if certs.is_empty() {
+            return Err(Error::MissingCertificate);
+        }
+        self.ca = Some(Arc::new(certs.into_boxed_slice()));
+        Ok(self)
+    }
+
+    pub fn with_client_certificate(mut self, cert: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let certs = X509::stack_from_pem(cert).map_err(Error::Certificate)?;
+        if certs.is_empty() {
+            return Err(Error::MissingCertificate);
+        }
+        let key = PKey::private_key_from_pem(key).map_err(Error::PrivateKey)?;
+        self.client_certificate = Some(Arc::new(CertKey::new(certs, key)));
+        Ok(self)
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    pub fn apply(&self, peer: &mut HttpPeer) {
+        peer.options.verify_cert = self.verify;
+        peer.options.verify_hostname = self.verify;
+        peer.options.ca = self.ca.clone();
+        peer.client_cert_key = self.client_certificate.clone();
+    }
+}
@@ -0,0 +1,487 @@
+mod session;
+
+use crate::k8s;
+use crate::k8s::claim::{self, Claim};
+use crate::load_balancer::StreamLoadBalancer;
+use crate::udp::session::Session;
+use anyhow::anyhow;
+use arc_swap::ArcSwapOption;
+use async_trait::async_trait;
+use crds::IngressRouteUDP;
+use dashmap::DashMap;
+use futures_util::future::BoxFuture;
+use k8s_openapi::api::core::v1::Endpoints;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{Api, Resource};
+use log::{debug, error, info, warn};
+use pingora::server::ShutdownWatch;
+use pingora::services::background::BackgroundService;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+
+const DEFAULT_IDLE_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_MAX_SESSIONS: usize = 10_000;
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+pub struct Route {
+    name: String,
+    created: DateTime<Utc>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    load_balancer: StreamLoadBalancer,
+    watch: Arc<Notify>,
+}
+
+impl Claim for Route {
+    fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedRouter(Arc<Router>);
+
+impl SharedRouter {
+    pub fn new(router: Router) -> Self {
+        Self(Arc::new(router))
+    }
+}
+
+#[async_trait]
+impl BackgroundService for SharedRouter {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        let address = format!("[::]:{}", self.0.port);
+        let listener = match UdpSocket::bind(&address).await {
+            Ok(socket) => Arc::new(socket),
+            Err(e) => {
+                error!("Unable to bind UDP entry point on {}: {}", address, e);
+                return;
+            }
+        };
+        info!("UDP entry point listening on {}", address);
+
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    info!("Stopping UDP entry point on {}", address);
+                    break;
+                }
+                result = listener.recv_from(&mut buf) => match result {
+                    Ok((len, client)) => {
+                        if let Err(e) = self.0.forward(&listener, client, &buf[..len]).await {
+                            debug!("Dropping UDP datagram from {}: {}", client, e);
+                        }
+                    }
+                    Err(e) => error!("Unable to receive UDP datagram: {}", e),
+                }
+            }
+        }
+    }
+}
+
+pub struct Router {
+    port: u16,
+    route: ArcSwapOption<Route>,
+    claims: DashMap<String, Arc<Route>>,
+    sessions: Arc<DashMap<SocketAddr, Arc<Session>>>,
+}
+
+impl Router {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            route: ArcSwapOption::empty(),
+            claims: DashMap::new(),
+            sessions: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn update_route_tables(
+        route_tables: Arc<DashMap<String, SharedRouter>>,
+    ) -> impl Fn(
+        kube::client::Client,
+        IngressRouteUDP,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>>
+           + Send
+           + Sync
+           + 'static {
+        move |k8s_client, route| {
+            let route_tables = route_tables.clone();
+            Box::pin(async move {
+                if let Some(router) = route_tables
+                    .get(&route.spec.entrypoint)
+                    .map(|v| v.value().clone())
+                {
+                    return router.0.update_route(k8s_client, route).await;
+                }
+
+                Ok(())
+            })
+        }
+    }
+
+    pub fn delete_routes(
+        route_tables: Arc<DashMap<String, SharedRouter>>,
+    ) -> impl Fn(
+        kube::client::Client,
+        IngressRouteUDP,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>>
+           + Send
+           + Sync
+           + 'static {
+        move |_, route| {
+            if let Some(route_id) = route.meta().uid.as_deref() {
+                route_tables
+                    .iter()
+                    .for_each(|router| router.0.delete_route(route_id));
+            }
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn update_route(
+        &self,
+        k8s_client: kube::Client,
+        route: IngressRouteUDP,
+    ) -> Result<(), anyhow::Error> {
+        let route_namespace = route.meta().namespace.clone().unwrap();
+        let route_name = route_name(&route);
+        let service = &route.spec.route.service;
+        let namespace = service.namespace.clone().unwrap_or(route_namespace);
+        let api = Api::<Endpoints>::namespaced(k8s_client.clone(), &namespace);
+        let ep = api.get(&service.name).await.map_err(|e| {
+            anyhow!(
+                "unable to get endpoints for service {}: {}",
+                service.name,
+                e
+            )
+        })?;
+        let load_balancer =
+            StreamLoadBalancer::new(k8s::endpoints::get_addresses(ep, service.port))?;
+
+        let watch = Arc::new(Notify::new());
+        let lb = load_balancer.clone();
+        let sessions = self.sessions.clone();
+        k8s::endpoints::watch(
+            k8s_client,
+            namespace,
+            service.name.clone(),
+            service.port,
+            watch.clone(),
+            move |addresses| match lb.update(addresses) {
+                Ok(_) => {
+                    debug!("UDP load balancer updated with new endpoint addresses");
+                    close_orphaned_sessions(&sessions, &route_name, &lb);
+                }
+                Err(e) => error!(
+                    "Unable to update UDP load balancer with new endpoints: {}",
+                    e
+                ),
+            },
+        );
+
+        self.apply(&route, load_balancer, watch);
+        Ok(())
+    }
+
+    fn apply(
+        &self,
+        route: &IngressRouteUDP,
+        load_balancer: StreamLoadBalancer,
+        watch: Arc<Notify>,
+    ) {
+        let route_meta = route.meta();
+        let route_id = route_meta.uid.clone().unwrap();
+        let route = Route {
+            name: route_name(route),
+            created: claim::created(route),
+            idle_timeout: Duration::from_secs(
+                route
+                    .spec
+                    .route
+                    .idle_timeout_seconds
+                    .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECONDS),
+            ),
+            max_sessions: route
+                .spec
+                .route
+                .max_sessions
+                .unwrap_or(DEFAULT_MAX_SESSIONS),
+            load_balancer,
+            watch,
+        };
+        if let Some(previous) = self.claims.insert(route_id, Arc::new(route)) {
+            previous.watch.notify_one();
+        }
+        self.resolve();
+    }
+
+    fn delete_route(&self, route_id: &str) {
+        if let Some((_, previous)) = self.claims.remove(route_id) {
+            previous.watch.notify_one();
+            self.resolve();
+        }
+    }
+
+    fn resolve(&self) {
+        let claims = self
+            .claims
+            .iter()
+            .map(|claim| claim.value().clone())
+            .collect();
+        let Some((route, ignored)) = claim::oldest(claims) else {
+            self.route.store(None);
+            return;
+        };
+        for ignored in ignored {
+            warn!(
+                "Ignoring IngressRouteUDP {}: entry point {} is already routed by {}",
+                ignored.name, self.port, route.name
+            );
+        }
+        self.route.store(Some(route));
+    }
+
+    async fn forward(
+        &self,
+        listener: &Arc<UdpSocket>,
+        client: SocketAddr,
+        datagram: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        let session = match self.sessions.get(&client).map(|s| s.value().clone()) {
+            Some(session) => session,
+            None => self.open_session(listener, client).await?,
+        };
+        session.touch();
+        session.upstream.send(datagram).await?;
+        Ok(())
+    }
+
+    async fn open_session(
+        &self,
+        listener: &Arc<UdpSocket>,
+        client: SocketAddr,
+    ) -> Result<Arc<Session>, anyhow::Error> {
+        let route = self
+            .route
+            .load_full()
+            .ok_or_else(|| anyhow!("no UDP route configured for entry point"))?;
+        if self.sessions.len() >= route.max_sessions {
+            return Err(anyhow!(
+                "{} has reached its limit of {} sessions",
+                route.name,
+                route.max_sessions
+            ));
+        }
+        let backend = route
+            .load_balancer
+            .select(&[])
+            .ok_or_else(|| anyhow!("no upstream endpoints available for {}", route.name))?;
+        let addr = *backend
+            .addr
+            .as_inet()
+            .ok_or_else(|| anyhow!("invalid upstream address {}", backend.addr))?;
+        let local = match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let upstream = UdpSocket::bind(local).await?;
+        upstream.connect(addr).await?;
+
+        let session = Arc::new(Session::new(upstream, route.name.clone(), addr));
+        self.sessions.insert(client, session.clone());
+        debug!(
+            "Opened UDP session from {} to {} for {}",
+            client, addr, route.name
+        );
+
+        let listener = listener.clone();
+        let sessions = self.sessions.clone();
+        let relay = session.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                let idle = relay.idle_remaining(route.idle_timeout);
+                if idle.is_zero() {
+                    break;
+                }
+                let received = tokio::select! {
+                    _ = relay.closed() => break,
+                    received = tokio::time::timeout(idle, relay.upstream.recv(&mut buf)) => received,
+                };
+                match received {
+                    Ok(Ok(len)) => {
+                        relay.touch();
+                        if let Err(e) = listener.send_to(&buf[..len], client).await {
+                            warn!("Unable to send UDP datagram to {}: {}", client, e);
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        warn!("Unable to receive UDP datagram from {}: {}", addr, e);
+                        break;
+                    }
+                    Err(_) => continue,
+                }
+            }
+            sessions.remove_if(&client, |_, session| Arc::ptr_eq(session, &relay));
+            debug!("Closed UDP session from {} to {}", client, addr);
+        });
+        Ok(session)
+    }
+}
+
+fn route_name(route: &IngressRouteUDP) -> String {
+    format!(
+        "{}/{}",
+        route.meta().namespace.as_deref().unwrap_or_default(),
+        route.meta().name.as_deref().unwrap_or_default()
+    )
+}
+
+// Sessions stay pinned to the backend they were opened with, so close the ones whose backend
+// has left the route's endpoints and let the client's next datagram pick a new one.
+fn close_orphaned_sessions(
+    sessions: &DashMap<SocketAddr, Arc<Session>>,
+    route: &str,
+    load_balancer: &StreamLoadBalancer,
+) {
+    sessions.retain(|client, session| {
+        if session.route != route || load_balancer.contains(&session.backend) {
+            return true;
+        }
+        debug!(
+            "Closing UDP session from {} to {}: backend left {}",
+            client, session.backend, route
+        );
+        session.close();
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{close_orphaned_sessions, Router, SharedRouter};
+    use crate::k8s::endpoints::Address;
+    use crate::k8s::testing;
+    use crate::load_balancer::StreamLoadBalancer;
+    use crds::IngressRouteUDP;
+    use dashmap::DashMap;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use tokio::net::UdpSocket;
+    use tokio::sync::Notify;
+
+    fn address(upstream: &UdpSocket) -> Address {
+        Address {
+            ip: "127.0.0.1".into(),
+            port: upstream.local_addr().unwrap().port(),
+            pod: None,
+        }
+    }
+
+    fn apply(
+        router: &Router,
+        name: &str,
+        created: &str,
+        max_sessions: usize,
+        load_balancer: StreamLoadBalancer,
+    ) -> IngressRouteUDP {
+        let route: IngressRouteUDP = testing::resource(
+            "IngressRouteUDP",
+            name,
+            created,
+            serde_json::json!({
+                "entrypoint": "dns",
+                "route": {"service": {"name": "dns", "port": 53}, "maxSessions": max_sessions},
+            }),
+        );
+        router.apply(&route, load_balancer, Arc::new(Notify::new()));
+        route
+    }
+
+    fn served(router: &Router) -> Option<String> {
+        router.route.load().as_ref().map(|route| route.name.clone())
+    }
+
+    #[tokio::test]
+    async fn deleted_route_hands_the_entry_point_to_the_next_claim() {
+        let router = SharedRouter::new(Router::new(53));
+        let route_tables = Arc::new(DashMap::new());
+        route_tables.insert("dns".to_string(), router.clone());
+        let empty = || StreamLoadBalancer::new(Vec::new()).unwrap();
+        let new = apply(&router.0, "new", "2024-01-02T00:00:00Z", 1, empty());
+        let old = apply(&router.0, "old", "2024-01-01T00:00:00Z", 1, empty());
+        assert_eq!(served(&router.0).as_deref(), Some("default/old"));
+
+        let delete = Router::delete_routes(route_tables);
+        delete(testing::client(), old).await.unwrap();
+        assert_eq!(served(&router.0).as_deref(), Some("default/new"));
+        delete(testing::client(), new).await.unwrap();
+        assert_eq!(served(&router.0), None);
+    }
+
+    #[tokio::test]
+    async fn drops_datagrams_from_new_clients_once_full() {
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let router = Router::new(53);
+        let load_balancer = StreamLoadBalancer::new(vec![address(&upstream)]).unwrap();
+        apply(&router, "dns", "2024-01-01T00:00:00Z", 2, load_balancer);
+
+        for port in [1001, 1002] {
+            let client = format!("127.0.0.1:{}", port).parse().unwrap();
+            assert!(router.forward(&listener, client, b"query").await.is_ok());
+        }
+        let client = "127.0.0.1:1001".parse().unwrap();
+        assert!(router.forward(&listener, client, b"again").await.is_ok());
+        let client = "127.0.0.1:1003".parse().unwrap();
+        let err = router
+            .forward(&listener, client, b"query")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "default/dns has reached its limit of 2 sessions"
+        );
+        assert_eq!(router.sessions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sessions_move_off_a_backend_that_left() {
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let listener = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let router = Router::new(53);
+        let load_balancer = StreamLoadBalancer::new(vec![address(&first)]).unwrap();
+        apply(
+            &router,
+            "dns",
+            "2024-01-01T00:00:00Z",
+            10,
+            load_balancer.clone(),
+        );
+        let client: SocketAddr = "127.0.0.1:1001".parse().unwrap();
+        router.forward(&listener, client, b"query").await.unwrap();
+
+        load_balancer.update(vec![address(&first)]).unwrap();
+        close_orphaned_sessions(&router.sessions, "default/dns", &load_balancer);
+        assert_eq!(router.sessions.len(), 1);
+
+        load_balancer.update(vec![address(&second)]).unwrap();
+        close_orphaned_sessions(&router.sessions, "default/dns", &load_balancer);
+        assert!(router.sessions.is_empty());
+
+        router.forward(&listener, client, b"retry").await.unwrap();
+        let mut buf = [0; 16];
+        let (len, _) = second.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"retry");
+    }
+}
@@ -0,0 +1,41 @@
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+
+pub struct Session {
+    pub upstream: UdpSocket,
+    pub route: String,
+    pub backend: SocketAddr,
+    last_active: Mutex<Instant>,
+    closed: Notify,
+}
+
+impl Session {
+    pub fn new(upstream: UdpSocket, route: String, backend: SocketAddr) -> Self {
+        Self {
+            upstream,
+            route,
+            backend,
+            last_active: Mutex::new(Instant::now()),
+            closed: Notify::new(),
+        }
+    }
+
+    pub fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    pub fn idle_remaining(&self, idle_timeout: Duration) -> Duration {
+        idle_timeout.saturating_sub(self.last_active.lock().unwrap().elapsed())
+    }
+
+    pub fn close(&self) {
+        self.closed.notify_one();
+    }
+
+    pub async fn closed(&self) {
+        self.closed.notified().await
+    }
+}